@@ -0,0 +1,145 @@
+use std::{
+    marker::PhantomData,
+    sync::{Condvar, Mutex},
+};
+
+// Fixed-capacity channel: N slots allocated up front in a ring buffer, so the queue never reallocates.  What a push into a full channel does
+// is picked at compile time through the P marker type (Reject or Overwrite) instead of a runtime flag
+pub struct ArrayChannel<T, const N: usize, P: Overflow = Reject> {
+    ring: Mutex<Ring<T, N>>,
+    item_ready: Condvar,
+    _policy: PhantomData<P>,
+}
+
+struct Ring<T, const N: usize> {
+    slots: [Option<T>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<T, const N: usize> Ring<T, N> {
+    fn push_back(&mut self, message: T) {
+        self.slots[(self.head + self.len) % N] = Some(message);
+        self.len += 1;
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let message = self.slots[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        message
+    }
+}
+
+/// What push() does when the channel is full.  `Output` is what push() returns
+pub trait Overflow {
+    type Output<T>;
+
+    fn accepted<T>() -> Self::Output<T>;
+
+    // `overwrite` drops the oldest message from the full ring to make room for the new one, and hands the old one back
+    fn on_full<T>(message: T, overwrite: impl FnOnce(T) -> T) -> Self::Output<T>;
+}
+
+/// A full push() fails and returns the new message as Err
+pub struct Reject;
+
+/// A full push() evicts the oldest message and returns it as Some
+pub struct Overwrite;
+
+impl Overflow for Reject {
+    type Output<T> = Result<(), T>;
+
+    fn accepted<T>() -> Result<(), T> {
+        Ok(())
+    }
+
+    fn on_full<T>(message: T, _overwrite: impl FnOnce(T) -> T) -> Result<(), T> {
+        Err(message)
+    }
+}
+
+impl Overflow for Overwrite {
+    type Output<T> = Option<T>;
+
+    fn accepted<T>() -> Option<T> {
+        None
+    }
+
+    fn on_full<T>(message: T, overwrite: impl FnOnce(T) -> T) -> Option<T> {
+        Some(overwrite(message))
+    }
+}
+
+impl<T, const N: usize, P: Overflow> Default for ArrayChannel<T, N, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize, P: Overflow> ArrayChannel<T, N, P> {
+    pub const fn new() -> Self {
+        assert!(N > 0, "an ArrayChannel needs at least one slot!");
+        Self {
+            ring: Mutex::new(Ring {
+                slots: [const { None }; N],
+                head: 0,
+                len: 0,
+            }),
+            item_ready: Condvar::new(),
+            _policy: PhantomData,
+        }
+    }
+
+    pub fn push(&self, message: T) -> P::Output<T> {
+        let mut ring = self.ring.lock().unwrap();
+        if ring.len < N {
+            ring.push_back(message);
+            drop(ring);
+            self.item_ready.notify_one();
+            return P::accepted();
+        }
+        // Overwriting keeps the ring full, so there's nobody new to notify
+        P::on_full(message, |message| {
+            let oldest = ring.pop_front().unwrap();
+            ring.push_back(message);
+            oldest
+        })
+    }
+
+    pub fn pop(&self) -> T {
+        let mut ring = self.ring.lock().unwrap();
+        loop {
+            if let Some(message) = ring.pop_front() {
+                return message;
+            }
+            ring = self.item_ready.wait(ring).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_returns_the_new_message() {
+        let channel: ArrayChannel<u8, 2, Reject> = ArrayChannel::new();
+        assert_eq!(channel.push(1), Ok(()));
+        assert_eq!(channel.push(2), Ok(()));
+        assert_eq!(channel.push(3), Err(3));
+        assert_eq!([channel.pop(), channel.pop()], [1, 2]);
+    }
+
+    #[test]
+    fn overwrite_returns_the_oldest_message() {
+        let channel: ArrayChannel<u8, 2, Overwrite> = ArrayChannel::new();
+        assert_eq!(channel.push(1), None);
+        assert_eq!(channel.push(2), None);
+        assert_eq!(channel.push(3), Some(1));
+        assert_eq!([channel.pop(), channel.pop()], [2, 3]);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    array,
+    collections::VecDeque,
+    sync::{Condvar, Mutex},
+};
+
+// Priority channel for a small, fixed number of priority levels: one VecDeque per band instead of a heap, so both send and receive are O(1)
+// (receive just scans the LEVELS bands).  Band 0 is the highest priority, and messages within a band come out in the order they were sent
+pub struct BandedChannel<T, const LEVELS: usize> {
+    bands: Mutex<[VecDeque<T>; LEVELS]>,
+    item_ready: Condvar,
+}
+
+impl<T, const LEVELS: usize> Default for BandedChannel<T, LEVELS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const LEVELS: usize> BandedChannel<T, LEVELS> {
+    pub fn new() -> Self {
+        Self {
+            bands: Mutex::new(array::from_fn(|_| VecDeque::new())),
+            item_ready: Condvar::new(),
+        }
+    }
+
+    /// Panics if `level` isn't one of the channel's bands
+    pub fn send(&self, level: usize, message: T) {
+        assert!(level < LEVELS, "no such priority band!");
+        self.bands.lock().unwrap()[level].push_back(message);
+        self.item_ready.notify_one();
+    }
+
+    pub fn receive(&self) -> T {
+        let mut bands = self.bands.lock().unwrap();
+        loop {
+            if let Some(message) = bands.iter_mut().find_map(|band| band.pop_front()) {
+                return message;
+            }
+            bands = self.item_ready.wait(bands).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highest_band_comes_out_first() {
+        let channel: BandedChannel<&str, 3> = BandedChannel::new();
+        channel.send(2, "low");
+        channel.send(0, "high");
+        channel.send(1, "mid");
+        channel.send(0, "high again");
+        assert_eq!(
+            [
+                channel.receive(),
+                channel.receive(),
+                channel.receive(),
+                channel.receive()
+            ],
+            ["high", "high again", "mid", "low"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no such priority band!")]
+    fn out_of_range_band_panics() {
+        BandedChannel::<u8, 3>::new().send(3, 0);
+    }
+}
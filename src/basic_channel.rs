@@ -1,40 +1,3053 @@
 use std::{
-    collections::VecDeque,
-    sync::{Condvar, Mutex},
+    collections::{HashMap, VecDeque},
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    hint, mem,
+    ops::{Deref, DerefMut},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard, TryLockError,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle, Thread},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "smallvec")]
+use smallvec::SmallVec;
+#[cfg(feature = "linux")]
+use std::thread::ThreadId;
+#[cfg(feature = "async")]
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
 };
 
 // Basic channel implementation: VecDeque protected by a Mutex.  VecDeque acts as a queue of data (messages).
 // Senders add the message to the back of the queue and recipients pop from the front.  Receive operation is made blocking using a Condvar to notify
 // waiting receivers of a new message
 pub struct BasicChannel<T> {
+    // Unique per channel (clones included), to correlate log lines from many channels
+    id: u64,
     queue: Mutex<VecDeque<T>>,
     item_ready: Condvar,
+    // Most messages send() lets into the queue before blocking on space_ready; usize::MAX for an unbounded channel.  Everything that adds
+    // new messages is held back, except requeue_front() and move_matching(): refusing those could lose or deadlock on messages that are
+    // already in flight
+    capacity: usize,
+    space_ready: Condvar,
+    // Senders blocked on space_ready that need more than the one slot a single receive frees up (send_chunked(), send_2pc()).  While there
+    // are any, every freed slot wakes all senders, since a notify_one() landing on one of these could otherwise be wasted.  Only changed
+    // with the queue locked
+    multi_slot_waiters: AtomicUsize,
+    // Set by relieve_backpressure(): the capacity stops holding senders back until restore_backpressure()
+    backpressure_relieved: AtomicBool,
+    // Longest the queue has ever been, for sizing bounds.  Only ever grows, even once the queue drains
+    high_water: AtomicUsize,
+    throughput: Throughput,
+    sent: AtomicU64,
+    received: AtomicU64,
+    // Condvar wakeups that found the queue still empty
+    spurious_wakeups: AtomicU64,
+    // Receivers currently blocked on item_ready.  Only changed with the queue locked
+    blocked: AtomicUsize,
+    // Signalled when messages leave the queue, for close_and_wait().  Only notified while drain_waiters > 0, to keep it off the receive path
+    drained: Condvar,
+    drain_waiters: AtomicUsize,
+    // Copies of recently accepted send_dedup_ttl() messages, with when they were accepted
+    dedup: Mutex<DedupWindow<T>>,
+    // Tasks waiting on a backlog_cleared() future, woken whenever messages leave the queue so they can re-check its length.  How many are
+    // registered is mirrored in backlog_waiting (raised with the queue locked, like drain_waiters), so receives skip the lock when it's 0
+    #[cfg(feature = "async")]
+    backlog_wakers: Mutex<Vec<Waker>>,
+    #[cfg(feature = "async")]
+    backlog_waiting: AtomicUsize,
+    // Exponentially weighted moving average of how long receive() recently had to wait for a message, in nanoseconds
+    wait_ewma: AtomicU64,
+    // Threads blocked in a Select over this channel, and how many there are so send() can skip the lock when there are none
+    selectors: Mutex<Vec<Thread>>,
+    selecting: AtomicUsize,
+    // Receivers waiting in fair mode (new_fair()), longest waiting first, each on its own Condvar so a send can wake exactly that one.
+    // None in the default mode, where receivers all wait on item_ready
+    fair_waiters: Option<Mutex<VecDeque<Arc<FairWaiter>>>>,
+    // Where messages the channel turns away (send_dedup_ttl() duplicates) end up instead of being dropped, so lost work can be inspected
+    dead_letter: Option<Sender<T>>,
+}
+
+// If recent waits were shorter than this, receive() spins (briefly releasing the lock so senders can get in) before falling back to the
+// Condvar, since parking and being woken up would cost more than the wait itself.  Long waits go straight to the Condvar
+const SPIN_THRESHOLD: Duration = Duration::from_micros(50);
+const SPIN_LIMIT: u32 = 100;
+
+// Set once a sender has picked this waiter, and the Condvar it sleeps on
+type FairWaiter = (Mutex<bool>, Condvar);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+impl<T> Default for BasicChannel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T> BasicChannel<T> {
     pub fn new() -> Self {
+        Self::bounded(usize::MAX)
+    }
+
+    /// Channel whose send() blocks while `capacity` messages are queued, until a receive makes room.  A capacity of 0 could never accept
+    /// a message at all, so it's rejected instead of deadlocking the first send
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be at least 1!");
+        Self::bounded(capacity)
+    }
+
+    /// Channel that hands each message to the receiver that has been waiting longest, instead of whichever one the Condvar happens to
+    /// wake (which under load can be the same thread over and over).  Costs an allocation per wait, and receivers never spin
+    pub fn new_fair() -> Self {
+        let mut channel = Self::new();
+        channel.fair_waiters = Some(Mutex::new(VecDeque::new()));
+        channel
+    }
+
+    /// Forwards every message this channel turns away to `dead_letter` instead of dropping it
+    pub fn with_dead_letter(mut self, dead_letter: Sender<T>) -> Self {
+        self.dead_letter = Some(dead_letter);
+        self
+    }
+
+    fn bounded(capacity: usize) -> Self {
         Self {
+            id: next_id(),
             queue: Mutex::new(VecDeque::new()),
             item_ready: Condvar::new(),
+            capacity,
+            space_ready: Condvar::new(),
+            multi_slot_waiters: AtomicUsize::new(0),
+            backpressure_relieved: AtomicBool::new(false),
+            high_water: AtomicUsize::new(0),
+            throughput: Throughput::new(),
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            spurious_wakeups: AtomicU64::new(0),
+            blocked: AtomicUsize::new(0),
+            drained: Condvar::new(),
+            drain_waiters: AtomicUsize::new(0),
+            dedup: Mutex::new(DedupWindow::new()),
+            #[cfg(feature = "async")]
+            backlog_wakers: Mutex::new(Vec::new()),
+            #[cfg(feature = "async")]
+            backlog_waiting: AtomicUsize::new(0),
+            wait_ewma: AtomicU64::new(0),
+            selectors: Mutex::new(Vec::new()),
+            selecting: AtomicUsize::new(0),
+            fair_waiters: None,
+            dead_letter: None,
         }
     }
 
+    /// Reserves room for `cap` messages and fills it once with T::default() before clearing it again, which forces the OS to actually map
+    /// the pages: reserving alone leaves the first sends to take the page faults.  Trades a slower start for steadier send latency
+    pub fn preallocated(cap: usize) -> Self
+    where
+        T: Default,
+    {
+        let channel = Self::new();
+        let mut b = channel.queue.lock().unwrap();
+        b.reserve(cap);
+        let capacity = b.capacity();
+        b.resize_with(capacity, T::default);
+        b.clear();
+        drop(b);
+        channel
+    }
+
     pub fn send(&self, message: T) {
-        self.queue.lock().unwrap().push_back(message);
-        self.item_ready.notify_one();
+        let mut b = self.queue.lock().unwrap();
+        while self.is_full(b.len()) {
+            b = self.space_ready.wait(b).unwrap();
+        }
+        b.push_back(message);
+        self.record_sent(1, b.len());
+        drop(b);
+        self.message_ready();
+    }
+
+    /// Same as send(), but returns how long it took from the call until the message was enqueued: roughly zero unless the channel was full,
+    /// so producers can feed it into metrics to spot sustained backpressure
+    pub fn send_timed(&self, message: T) -> Duration {
+        let start = Instant::now();
+        let mut b = self.queue.lock().unwrap();
+        while self.is_full(b.len()) {
+            b = self.space_ready.wait(b).unwrap();
+        }
+        b.push_back(message);
+        let waited = start.elapsed();
+        self.record_sent(1, b.len());
+        drop(b);
+        self.message_ready();
+        waited
+    }
+
+    /// Enqueues the whole batch under one lock, then wakes one blocked receiver per message (or every blocked receiver if the batch is
+    /// bigger than that), so N messages never leave a receiver asleep while N are waiting.  The iterator runs with the queue locked.  On a
+    /// bounded channel that fills up, the messages pushed so far are handed out before waiting for room, so the batch can't wait on itself
+    pub fn send_all<I: IntoIterator<Item = T>>(&self, iter: I) {
+        let mut b = self.queue.lock().unwrap();
+        let mut pushed = 0;
+        for message in iter {
+            while self.is_full(b.len()) {
+                self.record_sent(pushed, b.len());
+                self.wake_receivers(pushed);
+                pushed = 0;
+                b = self.space_ready.wait(b).unwrap();
+            }
+            b.push_back(message);
+            pushed += 1;
+        }
+        self.record_sent(pushed, b.len());
+        drop(b);
+        self.wake_receivers(pushed);
+    }
+
+    /// Only enqueues the message if nothing else is pending, checked under the same lock as the push so two senders can't both succeed
+    pub fn send_if_empty(&self, message: T) -> Result<(), T> {
+        let mut b = self.queue.lock().unwrap();
+        if !b.is_empty() {
+            return Err(message);
+        }
+        b.push_back(message);
+        self.record_sent(1, b.len());
+        drop(b);
+        self.message_ready();
+        Ok(())
+    }
+
+    /// Drops the message (or hands it to the dead-letter channel) and returns false if an equal one was accepted less than `ttl` ago,
+    /// otherwise sends it.  A clone of every accepted message is kept for the window and compared with Eq, so distinct messages that happen
+    /// to hash the same are both sent.  Entries older than `ttl` are evicted on every call, which means the window is only as long as the
+    /// `ttl` of the latest call
+    pub fn send_dedup_ttl(&self, message: T, ttl: Duration) -> bool
+    where
+        T: Hash + Eq + Clone,
+    {
+        let now = Instant::now();
+        let mut window = self.dedup.lock().unwrap();
+        window.evict(now, ttl);
+        if !window.insert(&message, now) {
+            drop(window);
+            self.reject(message);
+            return false;
+        }
+        drop(window);
+        self.send(message);
+        true
+    }
+
+    /// Puts a message back at the front of the queue, for a consumer that failed to process it and wants it retried next rather than behind
+    /// everything sent since
+    pub fn requeue_front(&self, message: T) {
+        let mut b = self.queue.lock().unwrap();
+        b.push_front(message);
+        self.record_len(b.len());
+        drop(b);
+        self.message_ready();
+    }
+
+    /// Hands the locked queue to the caller for operations the other methods don't cover.  Nothing is woken up when the guard is simply
+    /// dropped: call notify() on it after adding messages, so blocked receivers find out about them
+    pub fn lock(&self) -> QueueGuard<'_, T> {
+        QueueGuard {
+            channel: self,
+            queue: self.queue.lock().unwrap(),
+        }
+    }
+
+    // Mutex has no timed lock, so this spins on try_lock() until the deadline, yielding in between so the thread holding the lock gets to
+    // run.  Bounds how long a send can be stuck even when the contention is on the mutex itself rather than on an empty queue
+    pub fn try_send_timeout(&self, message: T, dur: Duration) -> Result<(), T> {
+        let deadline = Instant::now() + dur;
+        loop {
+            match self.queue.try_lock() {
+                Ok(mut b) => {
+                    // If the queue is full, the rest of the time goes to waiting for a receive to make room
+                    while self.is_full(b.len()) {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            return Err(message);
+                        }
+                        b = self.space_ready.wait_timeout(b, deadline - now).unwrap().0;
+                    }
+                    b.push_back(message);
+                    self.record_sent(1, b.len());
+                    drop(b);
+                    self.message_ready();
+                    return Ok(());
+                }
+                Err(TryLockError::WouldBlock) => {
+                    if Instant::now() >= deadline {
+                        return Err(message);
+                    }
+                    thread::yield_now();
+                }
+                Err(TryLockError::Poisoned(_)) => panic!("queue mutex poisoned!"),
+            }
+        }
     }
 
     pub fn receive(&self) -> T {
+        let mut b = self.queue.lock().unwrap();
+        if let Some(message) = b.pop_front() {
+            self.record_received(1);
+            return message;
+        }
+        let start = Instant::now();
+        // A spinning receiver would keep cutting in front of the queued ones, so fair mode always waits its turn
+        let mut spins = if self.fair_waiters.is_none() && self.recent_wait() < SPIN_THRESHOLD {
+            SPIN_LIMIT
+        } else {
+            0
+        };
+        let message = loop {
+            if let Some(message) = b.pop_front() {
+                break message;
+            }
+            if spins > 0 {
+                spins -= 1;
+                drop(b);
+                hint::spin_loop();
+                b = self.queue.lock().unwrap();
+            } else {
+                b = self.wait(b);
+            }
+        };
+        self.record_received(1);
+        self.record_wait(start.elapsed());
+        message
+    }
+
+    /// Blocks for at most `dur`.  A message that shows up just as the time runs out still wins: the queue is checked once more before
+    /// giving up
+    pub fn receive_timeout(&self, dur: Duration) -> Result<T, RecvTimeoutError> {
+        self.receive_before(Instant::now() + dur)
+            .ok_or(RecvTimeoutError::Timeout)
+    }
+
+    /// Never blocks: returns None if the queue is empty right now
+    pub fn try_receive(&self) -> Option<T> {
+        let message = self.queue.lock().unwrap().pop_front()?;
+        self.record_received(1);
+        Some(message)
+    }
+
+    /// Takes the front message only if it matches `pred`; otherwise (or if the queue is empty) leaves the queue untouched and returns None.
+    /// Never blocks
+    pub fn receive_if<F: Fn(&T) -> bool>(&self, pred: F) -> Option<T> {
+        let mut b = self.queue.lock().unwrap();
+        if !pred(b.front()?) {
+            return None;
+        }
+        let message = b.pop_front();
+        self.record_received(1);
+        message
+    }
+
+    /// Blocks like receive(), but returns Err(Interrupted) if the process gets a SIGINT while waiting.  This is opt-in per call: while at
+    /// least one receive_interruptible() is running (on any channel), SIGINT goes to a handler that interrupts every one of them instead of
+    /// to whatever was installed before, and the previous handler (normally the default, terminate) is put back once the last one returns.
+    /// A SIGINT from before the call doesn't count
+    #[cfg(feature = "unix")]
+    pub fn receive_interruptible(&self) -> Result<T, Interrupted> {
+        let generation = SIGINT_GENERATION.load(Ordering::Relaxed);
+        let _handler = SigintHandler::install();
         let mut b = self.queue.lock().unwrap();
         loop {
             if let Some(message) = b.pop_front() {
+                self.record_received(1);
+                return Ok(message);
+            }
+            if SIGINT_GENERATION.load(Ordering::Relaxed) != generation {
+                return Err(Interrupted);
+            }
+            // A signal doesn't wake up a thread waiting on a Condvar, so the generation is polled between (timed) waits
+            b = self.wait_timeout(b, SIGINT_POLL_INTERVAL);
+        }
+    }
+
+    /// Best-effort polling: tries to receive up to `spins` times, and falls back to `default()` instead of blocking if nothing shows up
+    pub fn receive_or<F: FnOnce() -> T>(&self, spins: u32, default: F) -> T {
+        for _ in 0..spins {
+            if let Some(message) = self.try_receive() {
                 return message;
             }
-            b = self.item_ready.wait(b).unwrap();
+            hint::spin_loop();
+        }
+        default()
+    }
+
+    /// Blocks until `cond` holds for the queued messages, then drains and returns all of them.  The predicate is re-checked every time a
+    /// sender wakes this receiver up, so it can look at anything about the queue (total size, a sentinel value, ...).  Meant for a channel
+    /// with a single consumer: since send() only wakes one receiver, a wakeup that lands here while the predicate is still false is not passed on
+    pub fn receive_until_condition<F: Fn(&VecDeque<T>) -> bool>(&self, cond: F) -> Vec<T> {
+        let mut b = self.queue.lock().unwrap();
+        while !cond(&b) {
+            b = self.wait(b);
+        }
+        self.record_received(b.len());
+        b.drain(..).collect()
+    }
+
+    /// Blocks until at least one message is queued, then takes up to `max` of them in one go.  Batches of up to 8 stay inline in the SmallVec,
+    /// so the common case of pulling a few items doesn't allocate
+    #[cfg(feature = "smallvec")]
+    pub fn receive_batch_small(&self, max: usize) -> SmallVec<[T; 8]> {
+        let mut b = self.queue.lock().unwrap();
+        while b.is_empty() {
+            b = self.wait(b);
+        }
+        let n = max.min(b.len());
+        self.record_received(n);
+        b.drain(..n).collect()
+    }
+
+    /// Blocks until at least one message is queued, then takes up to `max` of them under that single lock acquisition, so a consumer pays
+    /// for locking once per batch instead of once per message.  The batch is in FIFO order: exactly the messages a run of receive() calls
+    /// would have returned, in the same order
+    pub fn receive_batch(&self, max: usize) -> Vec<T> {
+        let mut b = self.queue.lock().unwrap();
+        while b.is_empty() {
+            b = self.wait(b);
+        }
+        let n = max.min(b.len());
+        self.record_received(n);
+        b.drain(..n).collect()
+    }
+
+    /// Runs `f` on the next message while still holding the queue lock, so nothing else can be sent or received until `f` returns.  Only
+    /// meant for short work that has to happen atomically with the dequeue; slow processing belongs in receive_then()
+    pub fn receive_with<R, F: FnOnce(T) -> R>(&self, f: F) -> R {
+        self.receive_scope(&mut (), |_, message| f(message))
+    }
+
+    /// Like receive_with(), but `f` also gets the caller's `state`.  Since the dequeue and the update to `state` both happen under the queue
+    /// lock, that state doesn't need a lock of its own as long as it's only ever updated this way
+    pub fn receive_scope<S, R>(&self, state: &mut S, f: impl FnOnce(&mut S, T) -> R) -> R {
+        let mut b = self.queue.lock().unwrap();
+        loop {
+            if let Some(message) = b.pop_front() {
+                self.record_received(1);
+                return f(state, message);
+            }
+            b = self.wait(b);
+        }
+    }
+
+    /// Unlike receive_with(), the lock is released as soon as the message is popped and only then is `f` run, so senders and other receivers
+    /// carry on while a slow `f` is processing
+    pub fn receive_then<R, F: FnOnce(T) -> R>(&self, f: F) -> R {
+        f(self.receive())
+    }
+
+    /// Pops and processes messages one at a time until `budget` is used up or the queue is empty, and returns how many were processed.
+    /// The elapsed time is checked after each message, so a slow `process` can overrun the budget by one message.  Doesn't block
+    pub fn receive_batch_within_budget(
+        &self,
+        budget: Duration,
+        mut process: impl FnMut(T),
+    ) -> usize {
+        let start = Instant::now();
+        let mut processed = 0;
+        while start.elapsed() < budget {
+            // The lock is only held for the pop, not while `process` runs
+            let Some(message) = self.queue.lock().unwrap().pop_front() else {
+                break;
+            };
+            self.record_received(1);
+            process(message);
+            processed += 1;
+        }
+        processed
+    }
+
+    /// For shutting down a consumer without losing its work: moves everything still queued into `backup`, keeping the order.  Whatever
+    /// couldn't be handed over (because nobody is receiving from the backup anymore) is returned to the caller instead of being dropped.  A
+    /// bounded backup is filled as its receivers make room, like with send_all()
+    pub fn failover_drain(&self, backup: &Sender<T>) -> Vec<T> {
+        let remaining = mem::take(&mut *self.queue.lock().unwrap());
+        self.queue_shrank(remaining.len());
+        match backup.forward(remaining) {
+            Ok(()) => Vec::new(),
+            Err(remaining) => remaining.into(),
+        }
+    }
+
+    /// Moves every queued message matching `pred` to the back of `dst` (keeping their relative order), and returns how many were moved.
+    /// Both queues stay locked for the whole operation, so no receiver sees a message in both or in neither.  Moving into the same channel
+    /// is a no-op
+    pub fn move_matching<F: Fn(&T) -> bool>(&self, pred: F, dst: &BasicChannel<T>) -> usize {
+        if ptr::eq(self, dst) {
+            return 0;
+        }
+        let (mut from, mut to) = lock_pair(self, dst);
+        let before = to.len();
+        let mut kept = VecDeque::with_capacity(from.len());
+        for message in from.drain(..) {
+            if pred(&message) {
+                to.push_back(message);
+            } else {
+                kept.push_back(message);
+            }
+        }
+        *from = kept;
+        let moved = to.len() - before;
+        self.queue_shrank(moved);
+        dst.record_len(to.len());
+        drop((from, to));
+        dst.wake_receivers(moved);
+        moved
+    }
+
+    /// Counts the queued messages per class returned by `f`, without consuming any of them.  The queue stays locked while `f` runs over it
+    pub fn classify<K: Eq + Hash, F: Fn(&T) -> K>(&self, f: F) -> HashMap<K, usize> {
+        let mut counts = HashMap::new();
+        for message in self.queue.lock().unwrap().iter() {
+            *counts.entry(f(message)).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Removes and returns everything queued right now, in FIFO order, under a single lock.  Never blocks
+    pub fn drain(&self) -> Vec<T> {
+        let mut b = self.queue.lock().unwrap();
+        self.record_received(b.len());
+        b.drain(..).collect()
+    }
+
+    /// Pops everything queued right now under a single lock and folds it into `init`, in FIFO order.  Returns `init` untouched if the queue
+    /// is empty; never blocks.  The lock is held while `f` runs, so `f` should be cheap
+    pub fn drain_fold<B, F: FnMut(B, T) -> B>(&self, init: B, f: F) -> B {
+        let mut b = self.queue.lock().unwrap();
+        self.record_received(b.len());
+        b.drain(..).fold(init, f)
+    }
+
+    /// Future that resolves once at most `threshold` messages are queued, so a producer can wait for a calm period before its next burst
+    #[cfg(feature = "async")]
+    pub fn backlog_cleared(&self, threshold: usize) -> BacklogCleared<'_, T> {
+        BacklogCleared {
+            channel: self,
+            threshold,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    // The queue accessors below are point-in-time snapshots: the lock is released before they return, so sends and receives can change the
+    // answer right away.  Fine for dashboards, but waiting on is_empty() in a loop should be a blocking receive instead
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().unwrap().is_empty()
+    }
+
+    /// Safety valve for clearing a backlog without dropping anything: senders stop blocking on the capacity (blocked ones are let through
+    /// right away) until restore_backpressure().  No-op on an unbounded channel
+    pub fn relieve_backpressure(&self) {
+        self.backpressure_relieved.store(true, Ordering::Relaxed);
+        // Same as the liveness wakeups: locking first means a sender that just saw a full queue is already waiting on space_ready
+        drop(self.queue.lock().unwrap());
+        self.space_ready.notify_all();
+    }
+
+    /// Puts the capacity back in charge.  Nothing is dropped if the queue grew past it meanwhile: senders just block until receives have
+    /// brought it back below the capacity
+    pub fn restore_backpressure(&self) {
+        self.backpressure_relieved.store(false, Ordering::Relaxed);
+    }
+
+    // Waits on space_ready for a sender that needs more than one free slot, see multi_slot_waiters
+    fn wait_for_slots<'a>(&'a self, b: MutexGuard<'a, VecDeque<T>>) -> MutexGuard<'a, VecDeque<T>> {
+        self.multi_slot_waiters.fetch_add(1, Ordering::Relaxed);
+        let b = self.space_ready.wait(b).unwrap();
+        self.multi_slot_waiters.fetch_sub(1, Ordering::Relaxed);
+        b
+    }
+
+    // Read with the queue locked, in every loop that waits on space_ready
+    fn is_full(&self, len: usize) -> bool {
+        len >= self.capacity && !self.backpressure_relieved.load(Ordering::Relaxed)
+    }
+
+    /// None for an unbounded channel
+    pub fn capacity(&self) -> Option<usize> {
+        (self.capacity != usize::MAX).then_some(self.capacity)
+    }
+
+    /// How many more messages send() would accept right now without blocking; None for an unbounded channel
+    pub fn remaining_capacity(&self) -> Option<usize> {
+        let len = self.len();
+        self.capacity().map(|capacity| capacity.saturating_sub(len))
+    }
+
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water.load(Ordering::Relaxed)
+    }
+
+    /// All counters in one go.  Each one is read separately, so under concurrent traffic they can be slightly out of step with each other
+    pub fn metrics_snapshot(&self) -> Metrics {
+        let sent = self.sent.load(Ordering::Relaxed);
+        Metrics {
+            sent,
+            received: self.received.load(Ordering::Relaxed),
+            pending: self.queue.lock().unwrap().len(),
+            high_water: self.high_water_mark(),
+            bytes: sent * mem::size_of::<T>() as u64,
+            spurious_wakeups: self.spurious_wakeups.load(Ordering::Relaxed),
+        }
+    }
+
+    // Only for messages that were sent: moving messages between channels (transfer_all, move_matching) updates the high-water mark only
+    fn record_sent(&self, n: usize, len: usize) {
+        self.sent.fetch_add(n as u64, Ordering::Relaxed);
+        self.record_len(len);
+    }
+
+    // Called with the queue still locked, right after a push.  fetch_max is a compare-and-max loop, so concurrent senders racing to raise the
+    // mark can't overwrite a higher value with a lower one
+    fn record_len(&self, len: usize) {
+        self.high_water.fetch_max(len, Ordering::Relaxed);
+    }
+
+    /// Messages received per second, averaged over the last few seconds (or over the channel's lifetime, if it's younger than that)
+    pub fn throughput_per_sec(&self) -> f64 {
+        self.throughput.per_sec()
+    }
+
+    fn recent_wait(&self) -> Duration {
+        Duration::from_nanos(self.wait_ewma.load(Ordering::Relaxed))
+    }
+
+    // Each new wait counts for 1/8 of the average, so a change in workload takes over after a handful of receives
+    fn record_wait(&self, waited: Duration) {
+        let sample = waited.as_nanos().min(u64::MAX as u128) as u64;
+        let _ = self
+            .wait_ewma
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |avg| {
+                Some(avg - avg / 8 + sample / 8)
+            });
+    }
+
+    fn reject(&self, message: T) {
+        // If the dead-letter channel was closed the message is dropped, same as without one
+        if let Some(dead_letter) = &self.dead_letter {
+            let _ = dead_letter.try_send(message);
+        }
+    }
+
+    fn record_received(&self, n: usize) {
+        self.received.fetch_add(n as u64, Ordering::Relaxed);
+        self.throughput.record(n as u64);
+        self.queue_shrank(n);
+    }
+
+    // Called after `n` messages left the queue, whether they were received or moved elsewhere.  A single freed slot is only worth one blocked
+    // sender (unless some sender needs several); for more than that every sender re-checks, since there's no count of how many are waiting
+    fn queue_shrank(&self, n: usize) {
+        if self.capacity != usize::MAX {
+            match n {
+                0 => {}
+                1 if self.multi_slot_waiters.load(Ordering::Relaxed) == 0 => {
+                    self.space_ready.notify_one()
+                }
+                _ => self.space_ready.notify_all(),
+            }
+        }
+        if self.drain_waiters.load(Ordering::Relaxed) > 0 {
+            self.drained.notify_all();
+        }
+        #[cfg(feature = "async")]
+        if self.backlog_waiting.load(Ordering::Relaxed) > 0 {
+            let wakers = {
+                let mut wakers = self.backlog_wakers.lock().unwrap();
+                self.backlog_waiting.store(0, Ordering::Relaxed);
+                mem::take(&mut *wakers)
+            };
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+    }
+
+    // True if the queue became empty within `timeout`.  drain_waiters is raised with the queue locked, before the emptiness check, so a
+    // receive that empties the queue afterwards is guaranteed to see it and notify
+    fn wait_drained(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut b = self.queue.lock().unwrap();
+        self.drain_waiters.fetch_add(1, Ordering::Relaxed);
+        let drained = loop {
+            if b.is_empty() {
+                break true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break false;
+            }
+            b = self.drained.wait_timeout(b, deadline - now).unwrap().0;
+        };
+        self.drain_waiters.fetch_sub(1, Ordering::Relaxed);
+        drained
+    }
+
+    /// Number of receivers blocked waiting for a message right now; may already be stale when it returns
+    pub fn blocked_receivers(&self) -> usize {
+        self.blocked.load(Ordering::Relaxed)
+    }
+
+    // After enqueueing `k` messages at once: wakes one receiver per message, but no more than are blocked, so a batch neither leaves a message
+    // sitting while a receiver sleeps, nor wakes the whole herd for a couple of messages.  A receiver that was woken but hasn't relocked the
+    // queue yet still counts as blocked, so this can over-notify slightly but never under-notify
+    fn wake_receivers(&self, k: usize) {
+        match &self.fair_waiters {
+            Some(waiters) => Self::hand_off(waiters, k),
+            None => {
+                for _ in 0..k.min(self.blocked_receivers()) {
+                    self.item_ready.notify_one();
+                }
+            }
+        }
+        if k > 0 {
+            self.wake_selectors();
+        }
+    }
+
+    // After enqueueing a single message
+    fn message_ready(&self) {
+        match &self.fair_waiters {
+            Some(waiters) => Self::hand_off(waiters, 1),
+            None => self.item_ready.notify_one(),
+        }
+        self.wake_selectors();
+    }
+
+    // Wakes every blocked receiver so it can re-check the liveness of the split channel
+    fn wake_all(&self) {
+        self.item_ready.notify_all();
+        if let Some(waiters) = &self.fair_waiters {
+            Self::hand_off(waiters, usize::MAX);
+        }
+    }
+
+    // Fair mode: wakes the `k` receivers that have been waiting longest.  `woken` is set under the waiter's own mutex, so a receiver that
+    // has queued its token but isn't asleep yet still sees it
+    fn hand_off(waiters: &Mutex<VecDeque<Arc<FairWaiter>>>, k: usize) {
+        let woken: Vec<_> = {
+            let mut waiters = waiters.lock().unwrap();
+            let n = k.min(waiters.len());
+            waiters.drain(..n).collect()
+        };
+        for waiter in woken {
+            let (woken, ready) = &*waiter;
+            *woken.lock().unwrap() = true;
+            ready.notify_one();
+        }
+    }
+
+    // The token is queued while the queue is still locked, so a send that the caller's emptiness check missed finds it.  A receiver that
+    // is woken but beaten to the message by a newcomer goes to the back of the line again, which makes the order roughly FIFO rather than
+    // strictly.  A timed out receiver takes its token back out, unless a sender already took it to hand a message off: in that case the
+    // message is still in the queue for the caller's next check
+    fn wait_fair<'a>(
+        &'a self,
+        waiters: &Mutex<VecDeque<Arc<FairWaiter>>>,
+        b: MutexGuard<'a, VecDeque<T>>,
+        timeout: Option<Duration>,
+    ) -> (MutexGuard<'a, VecDeque<T>>, bool) {
+        let waiter = Arc::new(FairWaiter::default());
+        waiters.lock().unwrap().push_back(waiter.clone());
+        drop(b);
+        let (woken, ready) = &*waiter;
+        let woken = woken.lock().unwrap();
+        let timed_out = match timeout {
+            Some(dur) => {
+                let (woken, result) = ready
+                    .wait_timeout_while(woken, dur, |woken| !*woken)
+                    .unwrap();
+                drop(woken);
+                result.timed_out()
+            }
+            None => {
+                drop(ready.wait_while(woken, |woken| !*woken).unwrap());
+                false
+            }
+        };
+        if timed_out {
+            waiters
+                .lock()
+                .unwrap()
+                .retain(|queued| !Arc::ptr_eq(queued, &waiter));
+        }
+        (self.queue.lock().unwrap(), timed_out)
+    }
+
+    // Selectors aren't blocked on item_ready (they'd have to wait on several Condvars at once), so they're unparked separately.  They stay
+    // registered until their select is done, and re-check every channel when woken
+    fn wake_selectors(&self) {
+        if self.selecting.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        for thread in self.selectors.lock().unwrap().iter() {
+            thread.unpark();
+        }
+    }
+
+    // Registering happens before the selector checks the queues.  A send that the check missed pushes under the queue lock after that,
+    // so it sees `selecting` raised and unparks the selector
+    pub(crate) fn register_selector(&self, thread: Thread) {
+        self.selecting.fetch_add(1, Ordering::Relaxed);
+        self.selectors.lock().unwrap().push(thread);
+    }
+
+    pub(crate) fn unregister_selector(&self, thread: &Thread) {
+        let mut selectors = self.selectors.lock().unwrap();
+        if let Some(i) = selectors.iter().position(|t| t.id() == thread.id()) {
+            selectors.swap_remove(i);
+            self.selecting.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn wait<'a>(&'a self, b: MutexGuard<'a, VecDeque<T>>) -> MutexGuard<'a, VecDeque<T>> {
+        self.blocked.fetch_add(1, Ordering::Relaxed);
+        let b = match &self.fair_waiters {
+            Some(waiters) => self.wait_fair(waiters, b, None).0,
+            None => self.item_ready.wait(b).unwrap(),
+        };
+        self.blocked.fetch_sub(1, Ordering::Relaxed);
+        if b.is_empty() {
+            self.spurious_wakeups.fetch_add(1, Ordering::Relaxed);
+        }
+        b
+    }
+
+    fn wait_timeout<'a>(
+        &'a self,
+        b: MutexGuard<'a, VecDeque<T>>,
+        dur: Duration,
+    ) -> MutexGuard<'a, VecDeque<T>> {
+        self.blocked.fetch_add(1, Ordering::Relaxed);
+        let (b, timed_out) = match &self.fair_waiters {
+            Some(waiters) => self.wait_fair(waiters, b, Some(dur)),
+            None => {
+                let (b, timeout) = self.item_ready.wait_timeout(b, dur).unwrap();
+                (b, timeout.timed_out())
+            }
+        };
+        self.blocked.fetch_sub(1, Ordering::Relaxed);
+        if !timed_out && b.is_empty() {
+            self.spurious_wakeups.fetch_add(1, Ordering::Relaxed);
+        }
+        b
+    }
+
+    // Condvar::wait_timeout can wake up early (spuriously or for a message another receiver took), so the remaining time is recomputed from
+    // the deadline on every iteration rather than waiting the full duration again
+    fn receive_before(&self, deadline: Instant) -> Option<T> {
+        let mut b = self.queue.lock().unwrap();
+        loop {
+            if let Some(message) = b.pop_front() {
+                self.record_received(1);
+                return Some(message);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            b = self.wait_timeout(b, deadline - now);
         }
     }
 }
 
-// Downsides of this implementation: even if there are plenty of messages ready to be received, any send or receive operation will brifly block any other send or receive operation,
-// since they all have to lock the same mutex.  If VecDeque::push has to grow the capacity of the VecDeque, all sending and receiving threads will have to wait for that
-// one thread to finish the reallocation
+// Framing over a byte channel: large payloads are split into chunks no bigger than the transport allows, and put back together on the other side
+impl BasicChannel<Vec<u8>> {
+    /// Enqueues all chunks under a single lock, so chunks from concurrent senders can't interleave.  On a bounded channel that means waiting
+    /// until all of them fit at once; a payload with more chunks than the capacity waits for the queue to be empty instead, and then goes
+    /// over the capacity rather than never being sent.  Panics if `chunk` is 0
+    pub fn send_chunked(&self, data: Vec<u8>, chunk: usize) {
+        assert!(chunk > 0, "chunk size must be at least 1!");
+        let pieces = data.len().div_ceil(chunk);
+        let mut b = self.queue.lock().unwrap();
+        while pieces > 0 && !b.is_empty() && self.is_full(b.len() + pieces - 1) {
+            b = self.wait_for_slots(b);
+        }
+        for piece in data.chunks(chunk) {
+            b.push_back(piece.to_vec());
+        }
+        self.record_sent(pieces, b.len());
+        drop(b);
+        self.wake_receivers(pieces);
+    }
+
+    /// Blocks until `total_len` bytes have been received.  If the last chunk holds more than is needed, the rest stays at the front of the queue
+    pub fn receive_reassembled(&self, total_len: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(total_len);
+        let mut b = self.queue.lock().unwrap();
+        while data.len() < total_len {
+            match b.pop_front() {
+                Some(mut piece) => {
+                    let needed = total_len - data.len();
+                    // A chunk only counts as received once all of it has been used
+                    if piece.len() > needed {
+                        b.push_front(piece.split_off(needed));
+                    } else {
+                        self.record_received(1);
+                    }
+                    data.append(&mut piece);
+                }
+                None => b = self.wait(b),
+            }
+        }
+        data
+    }
+}
+
+// Multi-part messages: each fragment carries a `more` flag that's true for every fragment except the last one of its frame
+impl<T> BasicChannel<(T, bool)> {
+    /// Collects fragments until one with `more == false`, and returns the whole frame.  If the rest of a frame hasn't been sent yet it keeps
+    /// waiting for it.  Fragments are popped as they arrive, so a framed channel should only have a single consumer, or frames get split up
+    pub fn receive_frame(&self) -> Vec<T> {
+        let mut frame = Vec::new();
+        let mut b = self.queue.lock().unwrap();
+        loop {
+            match b.pop_front() {
+                Some((fragment, more)) => {
+                    self.record_received(1);
+                    frame.push(fragment);
+                    if !more {
+                        return frame;
+                    }
+                }
+                None => b = self.wait(b),
+            }
+        }
+    }
+}
+
+// Cloning takes a point-in-time snapshot: the source queue is locked just long enough to copy the messages that are queued at that moment.
+// The clone is a separate channel from then on, so messages sent to or received from either one afterwards don't show up in the other
+impl<T: Clone> Clone for BasicChannel<T> {
+    fn clone(&self) -> Self {
+        let queue = self.queue.lock().unwrap().clone();
+        Self {
+            id: next_id(),
+            high_water: AtomicUsize::new(queue.len()),
+            queue: Mutex::new(queue),
+            item_ready: Condvar::new(),
+            capacity: self.capacity,
+            space_ready: Condvar::new(),
+            multi_slot_waiters: AtomicUsize::new(0),
+            backpressure_relieved: AtomicBool::new(
+                self.backpressure_relieved.load(Ordering::Relaxed),
+            ),
+            throughput: Throughput::new(),
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            spurious_wakeups: AtomicU64::new(0),
+            blocked: AtomicUsize::new(0),
+            drained: Condvar::new(),
+            drain_waiters: AtomicUsize::new(0),
+            dedup: Mutex::new(DedupWindow::new()),
+            #[cfg(feature = "async")]
+            backlog_wakers: Mutex::new(Vec::new()),
+            #[cfg(feature = "async")]
+            backlog_waiting: AtomicUsize::new(0),
+            wait_ewma: AtomicU64::new(0),
+            selectors: Mutex::new(Vec::new()),
+            selecting: AtomicUsize::new(0),
+            fair_waiters: self
+                .fair_waiters
+                .as_ref()
+                .map(|_| Mutex::new(VecDeque::new())),
+            dead_letter: self.dead_letter.clone(),
+        }
+    }
+}
+
+// Received-message counts bucketed by whole seconds since the channel was created, kept in a small ring so old seconds are overwritten as
+// time moves on.  Instant is monotonic, so wall-clock adjustments can't scramble the buckets.  Each bucket packs its second (high 32 bits)
+// and count (low 32 bits) into one atomic, so recording on the receive path is a single fetch_update instead of another lock
+const THROUGHPUT_WINDOW: u64 = 5;
+
+struct Throughput {
+    start: Instant,
+    buckets: [AtomicU64; THROUGHPUT_WINDOW as usize],
+}
+
+impl Throughput {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            buckets: [const { AtomicU64::new(0) }; THROUGHPUT_WINDOW as usize],
+        }
+    }
+
+    // A bucket still holding an older second is restarted at `n` rather than added to
+    fn record(&self, n: u64) {
+        let second = self.start.elapsed().as_secs();
+        let bucket = &self.buckets[(second % THROUGHPUT_WINDOW) as usize];
+        let _ = bucket.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |packed| {
+            let count = if packed >> 32 == second {
+                packed as u32 as u64
+            } else {
+                0
+            };
+            Some(second << 32 | (count + n).min(u32::MAX as u64))
+        });
+    }
+
+    // The window is the current, partial second plus the full seconds before it
+    fn per_sec(&self) -> f64 {
+        let elapsed = self.start.elapsed();
+        let second = elapsed.as_secs();
+        let received: u64 = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .filter(|packed| (packed >> 32) + THROUGHPUT_WINDOW > second)
+            .map(|packed| packed as u32 as u64)
+            .sum();
+        let span = elapsed
+            .as_secs_f64()
+            .min((THROUGHPUT_WINDOW - 1) as f64 + elapsed.subsec_nanos() as f64 / 1e9);
+        if span == 0.0 {
+            return 0.0;
+        }
+        received as f64 / span
+    }
+}
+
+// The send_dedup_ttl() window.  Accepted messages are bucketed by hash and compared with Eq inside a bucket, and `expiry` lists the buckets
+// in the order they were added to, so eviction only looks at the entries that are actually old instead of scanning the whole map
+struct DedupWindow<T> {
+    seen: HashMap<u64, Vec<(T, Instant)>>,
+    expiry: VecDeque<(Instant, u64)>,
+}
+
+impl<T: Hash + Eq + Clone> DedupWindow<T> {
+    fn evict(&mut self, now: Instant, ttl: Duration) {
+        while let Some(&(accepted, key)) = self.expiry.front() {
+            if now.duration_since(accepted) < ttl {
+                break;
+            }
+            self.expiry.pop_front();
+            // May already be gone if an earlier call used a shorter ttl, which is fine
+            if let Some(bucket) = self.seen.get_mut(&key) {
+                bucket.retain(|(_, accepted)| now.duration_since(*accepted) < ttl);
+                if bucket.is_empty() {
+                    self.seen.remove(&key);
+                }
+            }
+        }
+    }
+
+    // False if an equal message is still in the window
+    fn insert(&mut self, message: &T, now: Instant) -> bool {
+        let mut hasher = DefaultHasher::new();
+        message.hash(&mut hasher);
+        let key = hasher.finish();
+        let bucket = self.seen.entry(key).or_default();
+        if bucket.iter().any(|(seen, _)| seen == message) {
+            return false;
+        }
+        bucket.push((message.clone(), now));
+        self.expiry.push_back((now, key));
+        true
+    }
+}
+
+impl<T> DedupWindow<T> {
+    fn new() -> Self {
+        Self {
+            seen: HashMap::new(),
+            expiry: VecDeque::new(),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub struct BacklogCleared<'a, T> {
+    channel: &'a BasicChannel<T>,
+    threshold: usize,
+}
+
+#[cfg(feature = "async")]
+impl<T> Future for BacklogCleared<'_, T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let b = self.channel.queue.lock().unwrap();
+        if b.len() <= self.threshold {
+            return Poll::Ready(());
+        }
+        // The waker is registered before the queue lock is released, so a receive that shrinks the queue after the length check above
+        // will find it and wake this task
+        let mut wakers = self.channel.backlog_wakers.lock().unwrap();
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+            self.channel.backlog_waiting.fetch_add(1, Ordering::Relaxed);
+        }
+        Poll::Pending
+    }
+}
+
+// Derefs to the VecDeque itself.  The sent/received counters only see messages that went through the channel's own methods, but the high
+// water mark is kept up to date by notify()
+pub struct QueueGuard<'a, T> {
+    channel: &'a BasicChannel<T>,
+    queue: MutexGuard<'a, VecDeque<T>>,
+}
+
+impl<T> QueueGuard<'_, T> {
+    /// Releases the lock and wakes up as many blocked receivers as there are messages queued (and anyone waiting for room in the queue or
+    /// for it to drain, in case messages were taken out)
+    pub fn notify(self) {
+        let len = self.queue.len();
+        self.channel.record_len(len);
+        drop(self.queue);
+        // There's no telling how many messages the caller took out
+        self.channel.queue_shrank(usize::MAX);
+        self.channel.wake_receivers(len);
+    }
+}
+
+impl<T> Deref for QueueGuard<'_, T> {
+    type Target = VecDeque<T>;
+
+    fn deref(&self) -> &VecDeque<T> {
+        &self.queue
+    }
+}
+
+impl<T> DerefMut for QueueGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut VecDeque<T> {
+        &mut self.queue
+    }
+}
+
+#[cfg(feature = "unix")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted;
+
+// Bumped by every SIGINT.  Each receive_interruptible() compares it against the value from when it started, so one signal reaches every
+// receiver that's waiting at the time, and none that only starts waiting afterwards
+#[cfg(feature = "unix")]
+static SIGINT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(feature = "unix")]
+const SIGINT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Only touches an atomic, which is one of the few things that's safe to do inside a signal handler
+#[cfg(feature = "unix")]
+extern "C" fn on_sigint(_: libc::c_int) {
+    SIGINT_GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+// How many receive_interruptible() calls are running, and the SIGINT action that was in place before the first of them installed on_sigint()
+#[cfg(feature = "unix")]
+static SIGINT_USERS: Mutex<(usize, Option<libc::sigaction>)> = Mutex::new((0, None));
+
+// Held for the duration of a receive_interruptible().  Dropping it (even while unwinding) puts the previous action back after the last one
+#[cfg(feature = "unix")]
+struct SigintHandler;
+
+#[cfg(feature = "unix")]
+impl SigintHandler {
+    fn install() -> Self {
+        let mut users = SIGINT_USERS.lock().unwrap();
+        if users.0 == 0 {
+            // SA_RESTART so that blocking syscalls elsewhere in the process don't start failing with EINTR because of us
+            let previous = unsafe {
+                let mut action: libc::sigaction = mem::zeroed();
+                action.sa_sigaction = on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                let mut previous: libc::sigaction = mem::zeroed();
+                assert_eq!(
+                    libc::sigaction(libc::SIGINT, &action, &mut previous),
+                    0,
+                    "couldn't install the SIGINT handler!"
+                );
+                previous
+            };
+            users.1 = Some(previous);
+        }
+        users.0 += 1;
+        Self
+    }
+}
+
+#[cfg(feature = "unix")]
+impl Drop for SigintHandler {
+    fn drop(&mut self) {
+        let mut users = SIGINT_USERS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        users.0 -= 1;
+        // Restoring a previously valid action can't fail, and there'd be nothing better to do about it here anyway
+        if users.0 == 0
+            && let Some(previous) = users.1.take()
+        {
+            unsafe { libc::sigaction(libc::SIGINT, &previous, ptr::null_mut()) };
+        }
+    }
+}
+
+// Counters of a BasicChannel, for exporting to dashboards.  `bytes` is the shallow size of everything sent (size_of::<T>() per message), so
+// it doesn't include heap data owned by the messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Metrics {
+    pub sent: u64,
+    pub received: u64,
+    pub pending: usize,
+    pub high_water: usize,
+    pub bytes: u64,
+    pub spurious_wakeups: u64,
+}
+
+impl<T> fmt::Debug for BasicChannel<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BasicChannel")
+            .field("id", &self.id)
+            .field("len", &self.queue.lock().unwrap().len())
+            .field("high_water", &self.high_water_mark())
+            .finish()
+    }
+}
+
+/// Moves every queued message from `src` to the back of `dst` in one go, instead of a receive/send round trip per message.  Never blocks:
+/// if `dst` is bounded only as many as fit are moved, oldest first, and the rest stay queued in `src`.  Returns how many were moved
+pub fn transfer_all<T>(src: &BasicChannel<T>, dst: &BasicChannel<T>) -> usize {
+    if ptr::eq(src, dst) {
+        return 0;
+    }
+    let (mut from, mut to) = lock_pair(src, dst);
+    let room = if dst.backpressure_relieved.load(Ordering::Relaxed) {
+        usize::MAX
+    } else {
+        dst.capacity.saturating_sub(to.len())
+    };
+    let moved = from.len().min(room);
+    to.extend(from.drain(..moved));
+    dst.record_len(to.len());
+    drop((from, to));
+    src.queue_shrank(moved);
+    dst.wake_receivers(moved);
+    moved
+}
+
+/// Enqueues `av` on `a` and `bv` on `b` as one step: both queues are locked before either message is pushed, so a consumer of either
+/// channel can never see one message of the pair without the other already being delivered.  If either channel is full it waits for room
+/// in both, without holding on to the other one's lock in the meantime.  Panics if `a` and `b` are the same channel
+pub fn send_2pc<T, U>(a: &BasicChannel<T>, av: T, b: &BasicChannel<U>, bv: U) {
+    assert_ne!(a.id, b.id, "send_2pc needs two different channels!");
+    let (mut qa, mut qb) = lock_pair(a, b);
+    loop {
+        // Room in the other channel may be gone again by the time this wakes up, so a wakeup from either one might not get used here
+        if a.is_full(qa.len()) {
+            drop(qb);
+            drop(a.wait_for_slots(qa));
+        } else if b.is_full(qb.len()) {
+            drop(qa);
+            drop(b.wait_for_slots(qb));
+        } else {
+            break;
+        }
+        (qa, qb) = lock_pair(a, b);
+    }
+    qa.push_back(av);
+    a.record_sent(1, qa.len());
+    qb.push_back(bv);
+    b.record_sent(1, qb.len());
+    drop((qa, qb));
+    a.message_ready();
+    b.message_ready();
+}
+
+// Both queues are always locked in the same (channel id) order, so two threads locking the same pair of channels in opposite argument order
+// can't deadlock.  Guards come back in argument order.  Callers have to rule out a and b being the same channel first, since locking the
+// same mutex twice deadlocks
+fn lock_pair<'a, T, U>(
+    a: &'a BasicChannel<T>,
+    b: &'a BasicChannel<U>,
+) -> (MutexGuard<'a, VecDeque<T>>, MutexGuard<'a, VecDeque<U>>) {
+    if a.id < b.id {
+        let first = a.queue.lock().unwrap();
+        (first, b.queue.lock().unwrap())
+    } else {
+        let first = b.queue.lock().unwrap();
+        (a.queue.lock().unwrap(), first)
+    }
+}
+
+// Downsides of this implementation: even if there are plenty of messages ready to be received, any send or receive operation will brifly block any other send or receive operation,
+// since they all have to lock the same mutex.  If VecDeque::push has to grow the capacity of the VecDeque, all sending and receiving threads will have to wait for that
+// one thread to finish the reallocation
+
+// Split version of BasicChannel: the channel is shared behind an Arc, and the Sender/Receiver handles keep count of how many of each are alive.
+// Those liveness counters let a receiver notice it's waiting on a channel nobody can send to anymore
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let a = Arc::new(Shared {
+        channel: BasicChannel::new(),
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+        closed: AtomicBool::new(false),
+    });
+    (
+        Sender { shared: a.clone() },
+        Receiver {
+            shared: a,
+            #[cfg(feature = "linux")]
+            affinity: None,
+        },
+    )
+}
+
+struct Shared<T> {
+    channel: BasicChannel<T>,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    // Set by Sender::close_and_wait().  A closed channel looks to receivers just like one whose senders are all gone
+    closed: AtomicBool,
+}
+
+impl<T> Shared<T> {
+    fn disconnected(&self) -> bool {
+        self.senders.load(Ordering::Relaxed) == 0 || self.closed.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    #[cfg(feature = "linux")]
+    affinity: Option<Affinity>,
+}
+
+// Core the receiving thread gets pinned to, and which thread that was last done for (and whether it worked), so it only costs a syscall the
+// first time a new thread receives
+#[cfg(feature = "linux")]
+struct Affinity {
+    core: usize,
+    pinned: Mutex<Option<(ThreadId, bool)>>,
+}
+
+#[cfg(feature = "linux")]
+impl Affinity {
+    fn new(core: usize) -> Self {
+        assert!(
+            core < libc::CPU_SETSIZE as usize,
+            "core is out of range for a cpu_set_t!"
+        );
+        Self {
+            core,
+            pinned: Mutex::new(None),
+        }
+    }
+
+    // Best effort: this is a performance knob, so a core that doesn't exist or isn't allowed just leaves the thread where it was.  The
+    // outcome is recorded for is_pinned() rather than retried, so a failing core doesn't cost a syscall on every receive
+    fn pin_current_thread(&self) {
+        let current = thread::current().id();
+        let mut pinned = self.pinned.lock().unwrap();
+        if matches!(*pinned, Some((thread, _)) if thread == current) {
+            return;
+        }
+        // CPU_SET indexes a fixed-size bitmask, which is why new() rejects cores past CPU_SETSIZE
+        let applied = unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            libc::CPU_SET(self.core, &mut set);
+            libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) == 0
+        };
+        *pinned = Some((current, applied));
+    }
+
+    fn is_pinned(&self) -> bool {
+        let current = thread::current().id();
+        matches!(*self.pinned.lock().unwrap(), Some((thread, true)) if thread == current)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WouldDeadlock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    Disconnected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    Timeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Heartbeat<T> {
+    Data(T),
+    Tick,
+}
+
+impl<T> Sender<T> {
+    /// Panics if the channel was closed with close_and_wait()
+    pub fn send(&self, message: T) {
+        if self.try_send(message).is_err() {
+            panic!("can't send on a closed channel!");
+        }
+    }
+
+    /// Like send(), but hands the message back instead of panicking if the channel was closed with close_and_wait() (through any sender)
+    pub fn try_send(&self, message: T) -> Result<(), T> {
+        if self.shared.closed.load(Ordering::Relaxed) {
+            return Err(message);
+        }
+        self.shared.channel.send(message);
+        Ok(())
+    }
+
+    /// Closes the channel for every sender, then waits until the consumer has drained what's still queued.  Returns whether that happened
+    /// within `timeout`, so a producer can know its work was picked up before it exits
+    pub fn close_and_wait(&self, timeout: Duration) -> bool {
+        self.shared.closed.store(true, Ordering::Relaxed);
+        // Receivers blocked on an empty queue need to find out that nothing more is coming, and a failover_drain() blocked on a full one
+        // that it should stop forwarding
+        drop(self.shared.channel.queue.lock().unwrap());
+        self.shared.channel.wake_all();
+        self.shared.channel.space_ready.notify_all();
+        self.shared.channel.wait_drained(timeout)
+    }
+
+    /// Number of queued messages at the time of the call; may already be stale when it returns
+    pub fn len_hint(&self) -> usize {
+        self.shared.channel.queue.lock().unwrap().len()
+    }
+
+    // Appends a whole batch, waiting for room like send_all() on a bounded channel.  If there's no receiver left to ever see them, or the
+    // channel was closed like try_send() refuses, whatever hasn't been appended yet is handed back (all of it, when that was already the
+    // case to begin with)
+    fn forward(&self, mut messages: VecDeque<T>) -> Result<(), VecDeque<T>> {
+        let channel = &self.shared.channel;
+        let mut b = channel.queue.lock().unwrap();
+        if self.refuses_forwarding() {
+            return Err(messages);
+        }
+        let mut pushed = 0;
+        while let Some(message) = messages.pop_front() {
+            while channel.is_full(b.len()) {
+                channel.record_sent(pushed, b.len());
+                channel.wake_receivers(pushed);
+                pushed = 0;
+                b = channel.space_ready.wait(b).unwrap();
+                // The last receiver leaving wakes up blocked senders (see Drop for Receiver), and so does close_and_wait()
+                if self.refuses_forwarding() {
+                    messages.push_front(message);
+                    return Err(messages);
+                }
+            }
+            b.push_back(message);
+            pushed += 1;
+        }
+        channel.record_sent(pushed, b.len());
+        drop(b);
+        channel.wake_receivers(pushed);
+        Ok(())
+    }
+
+    fn refuses_forwarding(&self) -> bool {
+        self.shared.receivers.load(Ordering::Relaxed) == 0
+            || self.shared.closed.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+// The last sender wakes every blocked receiver so they can re-check the liveness counters.  Taking the lock before notifying makes sure a
+// receiver that just saw senders > 0 is already waiting on the Condvar, and won't miss the notification
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::Relaxed) == 1 {
+            drop(self.shared.channel.queue.lock().unwrap());
+            self.shared.channel.wake_all();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Blocks for the next message.  Once every sender is gone (or the channel was closed) and the queue has been drained, returns
+    /// Err(RecvError::Disconnected) instead of blocking forever
+    pub fn receive(&self) -> Result<T, RecvError> {
+        #[cfg(feature = "linux")]
+        self.pin();
+        let mut b = self.shared.channel.queue.lock().unwrap();
+        loop {
+            if let Some(message) = b.pop_front() {
+                self.shared.channel.record_received(1);
+                return Ok(message);
+            }
+            if self.shared.disconnected() {
+                return Err(RecvError::Disconnected);
+            }
+            b = self.shared.channel.wait(b);
+        }
+    }
+
+    /// Same as receive(), but returns Err(WouldDeadlock) instead of blocking forever when the queue is empty, no senders are left and this is
+    /// the only receiver
+    pub fn receive_checked(&self) -> Result<T, WouldDeadlock> {
+        #[cfg(feature = "linux")]
+        self.pin();
+        let mut b = self.shared.channel.queue.lock().unwrap();
+        loop {
+            if let Some(message) = b.pop_front() {
+                self.shared.channel.record_received(1);
+                return Ok(message);
+            }
+            if self.shared.disconnected() && self.shared.receivers.load(Ordering::Relaxed) == 1 {
+                return Err(WouldDeadlock);
+            }
+            b = self.shared.channel.wait(b);
+        }
+    }
+
+    /// Blocks for the next message, and also reports whether the channel is finished: the bool is true when the queue is now empty and
+    /// every sender is gone, so a consumer can handle the final message and leave its loop in the same step.  Returns (None, true) if a
+    /// disconnect is all there is left to see
+    pub fn receive_status(&self) -> (Option<T>, bool) {
+        #[cfg(feature = "linux")]
+        self.pin();
+        let mut b = self.shared.channel.queue.lock().unwrap();
+        loop {
+            let disconnected = self.shared.disconnected();
+            if let Some(message) = b.pop_front() {
+                self.shared.channel.record_received(1);
+                return (Some(message), disconnected && b.is_empty());
+            }
+            if disconnected {
+                return (None, true);
+            }
+            b = self.shared.channel.wait(b);
+        }
+    }
+
+    /// Spawns `workers` threads that each receive and run `f` on messages until every sender is gone and the queue has been drained
+    pub fn process_with_pool(
+        self,
+        workers: usize,
+        f: impl Fn(T) + Send + Sync + 'static,
+    ) -> PoolHandle
+    where
+        T: Send + 'static,
+    {
+        let f = Arc::new(f);
+        let threads = (0..workers)
+            .map(|_| {
+                let receiver = self.clone();
+                let f = f.clone();
+                thread::spawn(move || {
+                    while let (Some(message), _) = receiver.receive_status() {
+                        f(message);
+                    }
+                })
+            })
+            .collect();
+        PoolHandle { threads }
+    }
+
+    pub fn requeue_front(&self, message: T) {
+        self.shared.channel.requeue_front(message);
+    }
+
+    /// Iterator over the messages queued right now, without blocking: ends as soon as the queue is (even momentarily) empty
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { receiver: self }
+    }
+
+    /// Iterator for timer-driven loops: yields Some(message) for each message, None for every `interval` without one, and ends once all
+    /// senders are gone and the queue is drained
+    pub fn timeout_iter(self, interval: Duration) -> TimeoutIter<T> {
+        TimeoutIter {
+            receiver: self,
+            interval,
+        }
+    }
+
+    /// Pins whichever thread receives through this handle to `core` (on its first receive), to keep the consumer's cache warm.  Panics if
+    /// `core` doesn't fit in a cpu_set_t (CPU_SETSIZE)
+    #[cfg(feature = "linux")]
+    pub fn with_affinity(mut self, core: usize) -> Self {
+        self.affinity = Some(Affinity::new(core));
+        self
+    }
+
+    /// Whether the calling thread was actually pinned by its last receive through this handle.  False before the first receive, without
+    /// with_affinity(), or if the kernel refused the core (offline, or outside the process's allowed set)
+    #[cfg(feature = "linux")]
+    pub fn is_pinned(&self) -> bool {
+        self.affinity
+            .as_ref()
+            .is_some_and(|affinity| affinity.is_pinned())
+    }
+
+    #[cfg(feature = "linux")]
+    fn pin(&self) {
+        if let Some(affinity) = &self.affinity {
+            affinity.pin_current_thread();
+        }
+    }
+
+    /// Number of queued messages at the time of the call; may already be stale when it returns
+    pub fn len_hint(&self) -> usize {
+        self.shared.channel.queue.lock().unwrap().len()
+    }
+
+    pub fn with_heartbeat(self, interval: Duration) -> HeartbeatReceiver<T> {
+        HeartbeatReceiver {
+            receiver: self,
+            interval,
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.receivers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+            #[cfg(feature = "linux")]
+            affinity: self.affinity.as_ref().map(|a| Affinity::new(a.core)),
+        }
+    }
+}
+
+// Going down to a single receiver can also turn a blocked receive_checked() into a deadlock, so the remaining one gets woken up as well.
+// When the last one leaves, senders blocked on a full queue are woken so that failover_drain() can give up instead of waiting forever
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        match self.shared.receivers.fetch_sub(1, Ordering::Relaxed) {
+            2 => {
+                drop(self.shared.channel.queue.lock().unwrap());
+                self.shared.channel.wake_all();
+            }
+            1 => {
+                drop(self.shared.channel.queue.lock().unwrap());
+                self.shared.channel.space_ready.notify_all();
+            }
+            _ => {}
+        }
+    }
+}
+
+pub struct TimeoutIter<T> {
+    receiver: Receiver<T>,
+    interval: Duration,
+}
+
+impl<T> Iterator for TimeoutIter<T> {
+    type Item = Option<T>;
+
+    fn next(&mut self) -> Option<Option<T>> {
+        #[cfg(feature = "linux")]
+        self.receiver.pin();
+        let shared = &self.receiver.shared;
+        let deadline = Instant::now() + self.interval;
+        let mut b = shared.channel.queue.lock().unwrap();
+        loop {
+            if let Some(message) = b.pop_front() {
+                shared.channel.record_received(1);
+                return Some(Some(message));
+            }
+            if shared.disconnected() {
+                return None;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Some(None);
+            }
+            b = shared.channel.wait_timeout(b, deadline - now);
+        }
+    }
+}
+
+// `for message in receiver` blocks for each message and ends cleanly once every sender is gone and the queue is drained
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        Iter { receiver: self }
+    }
+}
+
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+pub struct Iter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+pub struct TryIter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.receive().ok()
+    }
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.receive().ok()
+    }
+}
+
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.shared.channel.try_receive()
+    }
+}
+
+impl Receiver<f64> {
+    pub fn windowed_mean(self, window: usize) -> WindowedReceiver {
+        assert!(window > 0, "the window needs at least one value!");
+        WindowedReceiver {
+            receiver: self,
+            window,
+            values: VecDeque::with_capacity(window),
+        }
+    }
+}
+
+// Moving average over the stream: each receive() blocks for the next value and returns the mean of the last `window` values received,
+// or of all of them while fewer than `window` have arrived
+pub struct WindowedReceiver {
+    receiver: Receiver<f64>,
+    window: usize,
+    values: VecDeque<f64>,
+}
+
+impl WindowedReceiver {
+    pub fn receive(&mut self) -> Result<f64, RecvError> {
+        let value = self.receiver.receive()?;
+        if self.values.len() == self.window {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+        // Summed from scratch each time instead of keeping a running sum, which would slowly accumulate rounding error
+        Ok(self.values.iter().sum::<f64>() / self.values.len() as f64)
+    }
+}
+
+// Minimal executor: the messages are closures, and the receiving side runs them in the order they were submitted
+pub type Task = Box<dyn FnOnce() + Send>;
+
+pub type TaskChannel = BasicChannel<Task>;
+
+impl BasicChannel<Task> {
+    pub fn submit(&self, f: impl FnOnce() + Send + 'static) {
+        self.send(Box::new(f));
+    }
+}
+
+impl Sender<Task> {
+    pub fn submit(&self, f: impl FnOnce() + Send + 'static) {
+        self.send(Box::new(f));
+    }
+}
+
+impl Receiver<Task> {
+    /// Runs every submitted task until all senders are gone and the queue is drained.  A task that panics is abandoned, but the loop
+    /// carries on with the next one
+    pub fn run_loop(self) {
+        while let (Some(task), _) = self.receive_status() {
+            // The task is consumed by the call, so nothing it could have left half-updated is observed afterwards
+            let _ = panic::catch_unwind(AssertUnwindSafe(task));
+        }
+    }
+}
+
+pub struct PoolHandle {
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl PoolHandle {
+    /// Waits for all workers to finish, which happens once the channel is disconnected and drained.  Panics if a worker panicked
+    pub fn join(self) {
+        for thread in self.threads {
+            thread.join().unwrap();
+        }
+    }
+}
+
+// Receiver for liveness streams: every `interval` that passes without a message produces a Tick, so the consumer can tell a quiet producer
+// from a dead one.  A tick doesn't end anything, the next receive() goes back to waiting for real data
+pub struct HeartbeatReceiver<T> {
+    receiver: Receiver<T>,
+    interval: Duration,
+}
+
+impl<T> HeartbeatReceiver<T> {
+    /// Err(RecvError::Disconnected) once every sender is gone and the queue has been drained, like Receiver::receive()
+    pub fn receive(&self) -> Result<Heartbeat<T>, RecvError> {
+        #[cfg(feature = "linux")]
+        self.receiver.pin();
+        let shared = &self.receiver.shared;
+        let deadline = Instant::now() + self.interval;
+        let mut b = shared.channel.queue.lock().unwrap();
+        loop {
+            if let Some(message) = b.pop_front() {
+                shared.channel.record_received(1);
+                return Ok(Heartbeat::Data(message));
+            }
+            if shared.disconnected() {
+                return Err(RecvError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(Heartbeat::Tick);
+            }
+            b = shared.channel.wait_timeout(b, deadline - now);
+        }
+    }
+
+    pub fn into_inner(self) -> Receiver<T> {
+        self.receiver
+    }
+}
+
+// Producer-side batching: individual pushes collect in a local buffer that goes out as one Vec<T> message once it holds `max_batch` items, or
+// once `max_delay` has passed since its first item.  The delay is checked lazily on push (there's no timer thread), so a batch that stops
+// getting pushes sits in the buffer until flush() or drop.  Once the channel has been closed, flush() (and a push that flushes) hands the
+// batch back, and whatever is still buffered when the BatchingSender is dropped is lost
+pub struct BatchingSender<T> {
+    sender: Sender<Vec<T>>,
+    buffer: Vec<T>,
+    max_batch: usize,
+    max_delay: Duration,
+    first_push: Option<Instant>,
+}
+
+impl<T> BatchingSender<T> {
+    pub fn new(sender: Sender<Vec<T>>, max_batch: usize, max_delay: Duration) -> Self {
+        Self {
+            sender,
+            buffer: Vec::with_capacity(max_batch),
+            max_batch,
+            max_delay,
+            first_push: None,
+        }
+    }
+
+    pub fn push(&mut self, message: T) -> Result<(), Vec<T>> {
+        let first_push = *self.first_push.get_or_insert_with(Instant::now);
+        self.buffer.push(message);
+        if self.buffer.len() >= self.max_batch || first_push.elapsed() >= self.max_delay {
+            return self.flush();
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Vec<T>> {
+        self.first_push = None;
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let batch = mem::replace(&mut self.buffer, Vec::with_capacity(self.max_batch));
+        self.sender.try_send(batch)
+    }
+}
+
+// Must not panic, so a closed channel just means the buffered messages are dropped along with the BatchingSender
+impl<T> Drop for BatchingSender<T> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "async")]
+    use crate::test_util::block_on;
+
+    #[test]
+    fn clone_is_a_snapshot() {
+        let channel = BasicChannel::new();
+        for i in 0..3 {
+            channel.send(i);
+        }
+        let snapshot = channel.clone();
+        assert_eq!(channel.drain(), [0, 1, 2]);
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(snapshot.drain(), [0, 1, 2]);
+        assert_ne!(channel.id(), snapshot.id());
+    }
+
+    #[test]
+    fn lone_receiver_without_senders_would_deadlock() {
+        let (sender, receiver) = channel();
+        sender.send(1);
+        drop(sender);
+        // What was sent before the drop still comes through
+        assert_eq!(receiver.receive_checked(), Ok(1));
+        assert_eq!(receiver.receive_checked(), Err(WouldDeadlock));
+    }
+
+    #[test]
+    fn chunked_payload_is_reassembled() {
+        let channel = BasicChannel::new();
+        let data: Vec<u8> = (0..10).collect();
+        channel.send_chunked(data.clone(), 4);
+        assert_eq!(channel.len(), 3);
+        assert_eq!(channel.receive_reassembled(10), data);
+        assert!(channel.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "chunk size must be at least 1!")]
+    fn zero_chunk_size_panics() {
+        BasicChannel::new().send_chunked(vec![1, 2, 3], 0);
+    }
+
+    #[test]
+    fn try_send_timeout_gives_up_on_a_held_lock() {
+        let channel = BasicChannel::new();
+        let locked = std::sync::Barrier::new(2);
+        let release = std::sync::Barrier::new(2);
+        thread::scope(|s| {
+            s.spawn(|| {
+                let guard = channel.lock();
+                locked.wait();
+                release.wait();
+                drop(guard);
+            });
+            locked.wait();
+            let start = Instant::now();
+            assert_eq!(
+                channel.try_send_timeout(7, Duration::from_millis(30)),
+                Err(7)
+            );
+            assert!(start.elapsed() >= Duration::from_millis(30));
+            release.wait();
+        });
+        assert_eq!(
+            channel.try_send_timeout(7, Duration::from_millis(30)),
+            Ok(())
+        );
+        assert_eq!(channel.try_receive(), Some(7));
+    }
+
+    #[test]
+    fn heartbeat_ticks_between_slow_sends() {
+        let (sender, receiver) = channel();
+        let heartbeat = receiver.with_heartbeat(Duration::from_millis(10));
+        thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..2 {
+                    thread::sleep(Duration::from_millis(50));
+                    sender.send(i);
+                }
+            });
+            let mut events = Vec::new();
+            while let Ok(event) = heartbeat.receive() {
+                events.push(event);
+            }
+            let data: Vec<_> = events
+                .iter()
+                .filter_map(|event| match event {
+                    Heartbeat::Data(message) => Some(*message),
+                    Heartbeat::Tick => None,
+                })
+                .collect();
+            assert_eq!(data, [0, 1]);
+            // A tick before each message, since each one takes several intervals to arrive
+            assert_eq!(events[0], Heartbeat::Tick);
+            let second = events.iter().position(|event| *event == Heartbeat::Data(1));
+            assert_eq!(events[second.unwrap() - 1], Heartbeat::Tick);
+        });
+    }
+
+    #[test]
+    fn transfer_all_moves_everything() {
+        let src = BasicChannel::new();
+        let dst = BasicChannel::new();
+        dst.send(0);
+        for i in 1..5 {
+            src.send(i);
+        }
+        assert_eq!(transfer_all(&src, &dst), 4);
+        assert!(src.is_empty());
+        assert_eq!(dst.drain(), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn transfer_all_stops_at_a_bounded_destination() {
+        let src = BasicChannel::new();
+        let dst = BasicChannel::with_capacity(3);
+        dst.send(0);
+        for i in 1..5 {
+            src.send(i);
+        }
+        assert_eq!(transfer_all(&src, &dst), 2);
+        assert_eq!(dst.drain(), [0, 1, 2]);
+        // Whatever didn't fit is still there, in order, for the next transfer
+        assert_eq!(transfer_all(&src, &dst), 2);
+        assert!(src.is_empty());
+        assert_eq!(dst.drain(), [3, 4]);
+    }
+
+    #[test]
+    fn high_water_mark_survives_draining() {
+        let channel = BasicChannel::new();
+        for i in 0..5 {
+            channel.send(i);
+        }
+        assert_eq!(channel.drain().len(), 5);
+        assert!(channel.is_empty());
+        assert_eq!(channel.high_water_mark(), 5);
+    }
+
+    #[test]
+    fn receive_then_runs_f_without_the_lock() {
+        let channel = BasicChannel::new();
+        channel.send(1);
+        let sent_during_f = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                // Only gets through while f is running if f doesn't hold the queue lock
+                channel.send(2);
+                sent_during_f.store(true, Ordering::Relaxed);
+            });
+            let observed = channel.receive_then(|message| {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while !sent_during_f.load(Ordering::Relaxed) && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                (message, sent_during_f.load(Ordering::Relaxed))
+            });
+            assert_eq!(observed, (1, true));
+        });
+        assert_eq!(channel.receive(), 2);
+    }
+
+    #[test]
+    fn send_if_empty_only_accepts_one_pending() {
+        let channel = BasicChannel::new();
+        assert_eq!(channel.send_if_empty(1), Ok(()));
+        assert_eq!(channel.send_if_empty(2), Err(2));
+        assert_eq!(channel.receive(), 1);
+        assert_eq!(channel.send_if_empty(3), Ok(()));
+    }
+
+    #[test]
+    fn receive_until_condition_waits_for_the_sum() {
+        let channel = BasicChannel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1..=5 {
+                    thread::sleep(Duration::from_millis(5));
+                    channel.send(i);
+                }
+            });
+            let batch = channel.receive_until_condition(|queue| queue.iter().sum::<i32>() > 10);
+            assert!(batch.iter().sum::<i32>() > 10);
+            // 1..=4 only adds up to 10, so the 5 has to be in there
+            assert_eq!(batch, [1, 2, 3, 4, 5]);
+        });
+    }
+
+    #[cfg(feature = "linux")]
+    fn allowed_cores() -> Vec<usize> {
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        let result =
+            unsafe { libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) };
+        assert_eq!(result, 0);
+        (0..libc::CPU_SETSIZE as usize)
+            .filter(|&core| unsafe { libc::CPU_ISSET(core, &set) })
+            .collect()
+    }
+
+    #[cfg(feature = "linux")]
+    #[test]
+    fn pinned_receiver_still_receives() {
+        let core = allowed_cores()[0];
+        let (sender, receiver) = channel();
+        let receiver = receiver.with_affinity(core);
+        sender.send("hello");
+        // On a thread of its own, so the test harness's thread isn't left pinned
+        thread::spawn(move || {
+            assert!(!receiver.is_pinned());
+            assert_eq!(receiver.receive(), Ok("hello"));
+            assert!(receiver.is_pinned());
+            assert_eq!(allowed_cores(), [core]);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[cfg(feature = "linux")]
+    #[test]
+    fn refused_core_leaves_the_thread_alone() {
+        let allowed = allowed_cores();
+        let Some(core) = (0..libc::CPU_SETSIZE as usize).find(|core| !allowed.contains(core))
+        else {
+            return;
+        };
+        let (sender, receiver) = channel();
+        let receiver = receiver.with_affinity(core);
+        sender.send(1);
+        thread::spawn(move || {
+            assert_eq!(receiver.receive(), Ok(1));
+            assert!(!receiver.is_pinned());
+            assert_eq!(allowed_cores(), allowed);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[cfg(feature = "linux")]
+    #[test]
+    #[should_panic(expected = "core is out of range for a cpu_set_t!")]
+    fn core_past_cpu_setsize_panics() {
+        let _ = channel::<()>().1.with_affinity(5000);
+    }
+
+    #[test]
+    fn failover_drain_keeps_order() {
+        let primary = BasicChannel::new();
+        for i in 0..3 {
+            primary.send(i);
+        }
+        let (backup, backup_receiver) = channel();
+        assert!(primary.failover_drain(&backup).is_empty());
+        assert!(primary.is_empty());
+        assert_eq!(backup_receiver.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+        // Nobody can ever receive from a backup whose receivers are gone, so the messages come back instead
+        primary.send(3);
+        drop(backup_receiver);
+        assert_eq!(primary.failover_drain(&backup), [3]);
+    }
+
+    #[test]
+    fn budget_stops_mid_batch() {
+        let channel = BasicChannel::new();
+        for i in 0..10 {
+            channel.send(i);
+        }
+        let processed = channel.receive_batch_within_budget(Duration::from_millis(35), |_| {
+            thread::sleep(Duration::from_millis(10))
+        });
+        // Roughly three fit in the budget (the check is after each message); sleeps can overshoot, so allow a little slack
+        assert!((2..=4).contains(&processed), "processed {processed}");
+        assert_eq!(channel.len(), 10 - processed);
+    }
+
+    #[test]
+    fn batching_sender_flushes_on_count_and_on_drop() {
+        let (sender, receiver) = channel();
+        let mut batching = BatchingSender::new(sender, 3, Duration::from_secs(60));
+        for i in 0..5 {
+            batching.push(i).unwrap();
+        }
+        assert_eq!(receiver.len_hint(), 1);
+        drop(batching);
+        assert_eq!(
+            receiver.into_iter().collect::<Vec<_>>(),
+            [vec![0, 1, 2], vec![3, 4]]
+        );
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn small_batch_stays_inline() {
+        let channel = BasicChannel::new();
+        for i in 0..3 {
+            channel.send(i);
+        }
+        let batch = channel.receive_batch_small(8);
+        assert_eq!(batch.as_slice(), [0, 1, 2]);
+        assert!(!batch.spilled());
+    }
+
+    #[test]
+    fn throughput_tracks_a_steady_rate() {
+        let channel = BasicChannel::new();
+        // 10 messages every 50ms is 200/s.  Each burst is scheduled from the start time, so sleep overshoot doesn't add up
+        let start = Instant::now();
+        for burst in 1..=40 {
+            for i in 0..10 {
+                channel.send(i);
+            }
+            assert_eq!(channel.drain().len(), 10);
+            if let Some(wait) =
+                (start + Duration::from_millis(50) * burst).checked_duration_since(Instant::now())
+            {
+                thread::sleep(wait);
+            }
+        }
+        let rate = channel.throughput_per_sec();
+        assert!((150.0..=250.0).contains(&rate), "measured {rate}/s");
+    }
+
+    #[test]
+    fn adaptive_wait_delivers_in_both_regimes() {
+        let channel = BasicChannel::new();
+        // Seeded rather than measured, so each regime is exercised no matter how the test threads get scheduled
+        for (seed, regime) in [(0, "spin"), (u64::MAX / 8, "park")] {
+            channel.wait_ewma.store(seed, Ordering::Relaxed);
+            thread::scope(|s| {
+                s.spawn(|| {
+                    for i in 0..50 {
+                        if i % 10 == 0 {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                        channel.send(i);
+                    }
+                });
+                for i in 0..50 {
+                    assert_eq!(channel.receive(), i, "{regime} regime");
+                }
+            });
+        }
+        // Waits of a millisecond or more pull the average far past the threshold, whatever it started at
+        channel.wait_ewma.store(0, Ordering::Relaxed);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..30 {
+                    thread::sleep(Duration::from_millis(2));
+                    channel.send(i);
+                }
+            });
+            for i in 0..30 {
+                assert_eq!(channel.receive(), i);
+            }
+        });
+        assert!(channel.recent_wait() > SPIN_THRESHOLD);
+    }
+
+    #[test]
+    fn receive_or_falls_back_to_the_default() {
+        let channel = BasicChannel::new();
+        assert_eq!(channel.receive_or(100, || -1), -1);
+        channel.send(1);
+        assert_eq!(channel.receive_or(100, || -1), 1);
+        assert_eq!(channel.receive_or(0, || -1), -1);
+    }
+
+    #[test]
+    fn ids_are_unique_and_stable() {
+        let (a, b) = (BasicChannel::<()>::new(), BasicChannel::<()>::new());
+        assert_ne!(a.id(), b.id());
+        assert_eq!(a.id(), a.id());
+        assert!(format!("{a:?}").contains(&format!("id: {}", a.id())));
+    }
+
+    #[test]
+    fn move_matching_splits_by_predicate() {
+        let (src, dst) = (BasicChannel::new(), BasicChannel::new());
+        for i in 0..10 {
+            src.send(i);
+        }
+        assert_eq!(src.move_matching(|n| n % 2 == 0, &dst), 5);
+        assert_eq!(src.drain(), [1, 3, 5, 7, 9]);
+        assert_eq!(dst.drain(), [0, 2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn receive_status_flags_the_last_message() {
+        let (sender, receiver) = channel();
+        sender.send(1);
+        sender.send(2);
+        assert_eq!(receiver.receive_status(), (Some(1), false));
+        drop(sender);
+        assert_eq!(receiver.receive_status(), (Some(2), true));
+        assert_eq!(receiver.receive_status(), (None, true));
+    }
+
+    #[test]
+    fn relieved_backpressure_lets_sends_past_the_capacity() {
+        let channel = BasicChannel::with_capacity(2);
+        channel.send(0);
+        channel.send(1);
+        assert_eq!(
+            channel.try_send_timeout(2, Duration::from_millis(10)),
+            Err(2)
+        );
+        thread::scope(|s| {
+            // Already blocked when the valve opens, and let through by it
+            let blocked = s.spawn(|| channel.send(2));
+            thread::sleep(Duration::from_millis(20));
+            channel.relieve_backpressure();
+            blocked.join().unwrap();
+        });
+        for i in 3..6 {
+            assert_eq!(
+                channel.try_send_timeout(i, Duration::from_millis(10)),
+                Ok(())
+            );
+        }
+        assert_eq!(channel.len(), 6);
+        channel.restore_backpressure();
+        // Still over the capacity, so sends block again until the backlog drains
+        assert_eq!(
+            channel.try_send_timeout(6, Duration::from_millis(10)),
+            Err(6)
+        );
+        assert_eq!(channel.drain(), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(
+            channel.try_send_timeout(6, Duration::from_millis(10)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn send_timed_reports_the_wait_for_capacity() {
+        let channel = BasicChannel::with_capacity(1);
+        assert!(channel.send_timed(0) < Duration::from_millis(20));
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(30));
+                assert_eq!(channel.receive(), 0);
+            });
+            assert!(channel.send_timed(1) >= Duration::from_millis(25));
+        });
+        assert_eq!(channel.receive(), 1);
+    }
+
+    #[test]
+    fn receive_if_only_takes_a_matching_front() {
+        let channel = BasicChannel::new();
+        assert_eq!(channel.receive_if(|x| *x > 3), None);
+        channel.send(5);
+        channel.send(1);
+        assert_eq!(channel.receive_if(|x| *x > 3), Some(5));
+        assert_eq!(channel.receive_if(|x| *x > 3), None);
+        assert_eq!(channel.len(), 1);
+    }
+
+    #[test]
+    fn pool_processes_everything_then_exits() {
+        let (sender, receiver) = channel();
+        let handled = Arc::new(AtomicUsize::new(0));
+        let pool = receiver.process_with_pool(4, {
+            let handled = handled.clone();
+            move |_: u32| {
+                handled.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        for i in 0..100 {
+            sender.send(i);
+        }
+        // Dropping the last sender is what lets the workers finish
+        drop(sender);
+        pool.join();
+        assert_eq!(handled.load(Ordering::Relaxed), 100);
+    }
+
+    #[test]
+    fn metrics_snapshot_counts_traffic() {
+        let channel = BasicChannel::<u32>::new();
+        for i in 0..5 {
+            channel.send(i);
+        }
+        channel.receive();
+        channel.receive();
+        assert_eq!(
+            channel.metrics_snapshot(),
+            Metrics {
+                sent: 5,
+                received: 2,
+                pending: 3,
+                high_water: 5,
+                bytes: 20,
+                spurious_wakeups: 0,
+            }
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn metrics_serialize_to_json() {
+        let channel = BasicChannel::new();
+        channel.send(1u8);
+        let json = serde_json::to_value(channel.metrics_snapshot()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "sent": 1,
+                "received": 0,
+                "pending": 1,
+                "high_water": 1,
+                "bytes": 1,
+                "spurious_wakeups": 0,
+            })
+        );
+    }
+
+    // All SIGINT checks live in this one test, since the handler is process-wide and parallel tests raising SIGINT would see each other
+    #[cfg(feature = "unix")]
+    #[test]
+    fn sigint_interrupts_every_waiter_and_restores_the_previous_handler() {
+        fn current_handler() -> libc::sighandler_t {
+            unsafe {
+                let mut action: libc::sigaction = mem::zeroed();
+                assert_eq!(libc::sigaction(libc::SIGINT, ptr::null(), &mut action), 0);
+                action.sa_sigaction
+            }
+        }
+        let previous = current_handler();
+        let channel = Arc::new(BasicChannel::<u32>::new());
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let channel = channel.clone();
+                thread::spawn(move || channel.receive_interruptible())
+            })
+            .collect();
+        while SIGINT_USERS.lock().unwrap().0 < 3 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_ne!(current_handler(), previous);
+        unsafe { libc::raise(libc::SIGINT) };
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap(), Err(Interrupted));
+        }
+        assert_eq!(current_handler(), previous);
+
+        // The signal above was already delivered, so it mustn't interrupt a call that starts afterwards
+        let sender = {
+            let channel = channel.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                channel.send(7);
+            })
+        };
+        assert_eq!(channel.receive_interruptible(), Ok(7));
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn timeout_iter_ticks_between_messages() {
+        let (sender, receiver) = channel();
+        let producer = thread::spawn(move || {
+            sender.send(1);
+            thread::sleep(Duration::from_millis(100));
+            sender.send(2);
+        });
+        let items: Vec<_> = receiver.timeout_iter(Duration::from_millis(20)).collect();
+        producer.join().unwrap();
+        let first = items.iter().position(|item| *item == Some(1)).unwrap();
+        let second = items.iter().position(|item| *item == Some(2)).unwrap();
+        assert!(first < second);
+        assert!(items[first + 1..second].iter().all(Option::is_none));
+        assert!(second - first > 1);
+        assert_eq!(items.iter().flatten().count(), 2);
+    }
+
+    #[test]
+    fn requeue_front_is_received_next() {
+        let (sender, receiver) = channel();
+        for i in 0..3 {
+            sender.send(i);
+        }
+        let failed = receiver.receive().unwrap();
+        assert_eq!(failed, 0);
+        receiver.requeue_front(failed);
+        assert_eq!(
+            [receiver.receive(), receiver.receive(), receiver.receive()],
+            [Ok(0), Ok(1), Ok(2)]
+        );
+    }
+
+    #[test]
+    fn send_dedup_ttl_drops_duplicates_within_the_window() {
+        let channel = BasicChannel::new();
+        let ttl = Duration::from_millis(50);
+        assert!(channel.send_dedup_ttl("event", ttl));
+        assert!(!channel.send_dedup_ttl("event", ttl));
+        assert!(channel.send_dedup_ttl("other", ttl));
+        thread::sleep(ttl);
+        assert!(channel.send_dedup_ttl("event", ttl));
+        assert_eq!(channel.drain(), ["event", "other", "event"]);
+    }
+
+    #[test]
+    fn send_dedup_ttl_compares_colliding_messages_by_value() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Colliding(u32);
+        impl Hash for Colliding {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                0.hash(state);
+            }
+        }
+        let channel = BasicChannel::new();
+        let ttl = Duration::from_secs(60);
+        assert!(channel.send_dedup_ttl(Colliding(1), ttl));
+        assert!(channel.send_dedup_ttl(Colliding(2), ttl));
+        assert!(!channel.send_dedup_ttl(Colliding(1), ttl));
+        assert_eq!(channel.drain(), [Colliding(1), Colliding(2)]);
+    }
+
+    #[test]
+    fn classify_counts_without_consuming() {
+        let channel = BasicChannel::new();
+        channel.send_all([1, 2, 3, 4, 5, 7]);
+        let counts = channel.classify(|n| n % 2 == 0);
+        assert_eq!(counts[&true], 2);
+        assert_eq!(counts[&false], 4);
+        assert_eq!(channel.len(), 6);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn backlog_cleared_resolves_once_drained() {
+        let channel = BasicChannel::new();
+        channel.send_all([1, 2, 3]);
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                while channel.try_receive().is_some() {}
+            });
+            block_on(channel.backlog_cleared(1));
+            assert!(channel.len() <= 1);
+        });
+        block_on(channel.backlog_cleared(0));
+    }
+
+    #[test]
+    fn receive_frame_waits_for_the_last_fragment() {
+        let channel = BasicChannel::new();
+        channel.send(("a", true));
+        channel.send(("b", true));
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                channel.send(("c", false));
+                channel.send(("next", false));
+            });
+            assert_eq!(channel.receive_frame(), ["a", "b", "c"]);
+        });
+        assert_eq!(channel.receive_frame(), ["next"]);
+    }
+
+    #[test]
+    fn send_2pc_pairs_are_never_half_visible() {
+        const PAIRS: usize = 10_000;
+        let a = BasicChannel::new();
+        let b = BasicChannel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..PAIRS {
+                    send_2pc(&a, i, &b, i.to_string());
+                }
+            });
+            let mut drained = 0;
+            while drained < PAIRS {
+                let (mut qa, mut qb) = lock_pair(&a, &b);
+                assert_eq!(qa.len(), qb.len());
+                for (av, bv) in qa.drain(..).zip(qb.drain(..)) {
+                    assert_eq!(av.to_string(), bv);
+                    drained += 1;
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn batch_wakes_one_receiver_per_message() {
+        let channel = BasicChannel::new();
+        let received = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..5 {
+                s.spawn(|| {
+                    channel.receive();
+                    received.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            while channel.blocked_receivers() < 5 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            channel.send_all([1, 2, 3]);
+            while received.load(Ordering::Relaxed) < 3 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            thread::sleep(Duration::from_millis(50));
+            // A receiver woken for nothing would have found the queue empty and gone back to waiting
+            assert_eq!(received.load(Ordering::Relaxed), 3);
+            assert_eq!(channel.blocked_receivers(), 2);
+            assert_eq!(channel.spurious_wakeups.load(Ordering::Relaxed), 0);
+            channel.send_all([4, 5]);
+        });
+        assert_eq!(received.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn windowed_mean_averages_the_last_values() {
+        let (sender, receiver) = channel();
+        for value in [2.0, 4.0, 6.0, 8.0] {
+            sender.send(value);
+        }
+        let mut means = receiver.windowed_mean(2);
+        let outputs: Vec<_> = (0..4).map(|_| means.receive().unwrap()).collect();
+        assert_eq!(outputs, [2.0, 3.0, 5.0, 7.0]);
+        drop(sender);
+        assert_eq!(means.receive(), Err(RecvError::Disconnected));
+    }
+
+    #[test]
+    fn close_and_wait_returns_once_drained() {
+        let (sender, receiver) = channel();
+        let sibling = sender.clone();
+        for i in 0..3 {
+            sender.send(i);
+        }
+        let consumer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            receiver.into_iter().collect::<Vec<_>>()
+        });
+        assert!(sender.close_and_wait(Duration::from_secs(10)));
+        assert_eq!(consumer.join().unwrap(), [0, 1, 2]);
+        assert_eq!(sibling.try_send(3), Err(3));
+    }
+
+    #[test]
+    fn batching_sender_hands_the_batch_back_after_close() {
+        let (sender, _receiver) = channel();
+        let mut batching = BatchingSender::new(sender.clone(), 10, Duration::from_secs(60));
+        batching.push(1).unwrap();
+        // Nothing has reached the channel yet, so there's nothing to wait for
+        assert!(sender.close_and_wait(Duration::ZERO));
+        batching.push(2).unwrap();
+        assert_eq!(batching.flush(), Err(vec![1, 2]));
+        batching.push(3).unwrap();
+        // Dropping with a message still buffered mustn't panic
+        drop(batching);
+    }
+
+    #[test]
+    fn receive_scope_updates_state_in_dequeue_order() {
+        const MESSAGES: u32 = 10_000;
+        let channel = BasicChannel::new();
+        channel.send_all(0..MESSAGES);
+        // Every consumer pushes into the same Vec.  Its own lock is only there to make sharing it compile: since each push happens under the
+        // queue lock together with its dequeue, the pushes come out in exactly the order the messages were queued
+        let received = Mutex::new(Vec::new());
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..MESSAGES / 4 {
+                        channel.receive_scope(&mut &received, |received, message| {
+                            received.lock().unwrap().push(message)
+                        });
+                    }
+                });
+            }
+        });
+        assert_eq!(
+            received.into_inner().unwrap(),
+            (0..MESSAGES).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn preallocated_reserves_and_stays_empty() {
+        let channel = BasicChannel::<u64>::preallocated(1000);
+        assert!(channel.queue.lock().unwrap().capacity() >= 1000);
+        assert!(channel.is_empty());
+        assert_eq!(channel.metrics_snapshot().sent, 0);
+    }
+
+    #[test]
+    fn run_loop_runs_every_task_and_survives_panics() {
+        let (sender, receiver) = channel::<Task>();
+        let count = Arc::new(AtomicUsize::new(0));
+        for i in 0..4 {
+            let count = count.clone();
+            sender.submit(move || {
+                if i == 1 {
+                    panic!("bad task");
+                }
+                count.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        drop(sender);
+        receiver.run_loop();
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn queue_guard_inserts_in_the_middle() {
+        let channel = BasicChannel::new();
+        channel.send_all([1, 2, 4]);
+        thread::scope(|s| {
+            // The receiver is started while the guard is held, so it can only ever see the queue after the whole edit
+            let mut guard = channel.lock();
+            let receiver = s.spawn(|| {
+                let first = [channel.receive(), channel.receive(), channel.receive()];
+                (first, channel.receive())
+            });
+            let at = guard.iter().position(|&n| n == 4).unwrap();
+            guard.insert(at, 3);
+            guard.push_back(5);
+            guard.notify();
+            assert_eq!(receiver.join().unwrap(), ([1, 2, 3], 4));
+        });
+        assert_eq!(channel.drain(), [5]);
+    }
+
+    #[test]
+    fn send_chunked_waits_until_the_whole_payload_fits() {
+        let channel = BasicChannel::with_capacity(3);
+        channel.send(vec![0]);
+        channel.send(vec![0]);
+        let sent = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                channel.send_chunked(vec![1, 2, 3, 4], 2);
+                sent.store(true, Ordering::Relaxed);
+            });
+            thread::sleep(Duration::from_millis(50));
+            assert!(!sent.load(Ordering::Relaxed));
+            assert_eq!(channel.receive(), [0]);
+        });
+        assert_eq!(channel.drain(), [vec![0], vec![1, 2], vec![3, 4]]);
+    }
+
+    #[test]
+    fn send_chunked_larger_than_the_capacity_waits_for_an_empty_queue() {
+        let channel = BasicChannel::with_capacity(2);
+        channel.send(vec![0]);
+        thread::scope(|s| {
+            s.spawn(|| channel.send_chunked(vec![1, 2, 3], 1));
+            thread::sleep(Duration::from_millis(50));
+            assert_eq!(channel.len(), 1);
+            assert_eq!(channel.receive(), [0]);
+        });
+        assert_eq!(channel.drain(), [vec![1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn send_2pc_waits_for_room_in_both() {
+        let a = BasicChannel::with_capacity(1);
+        let b = BasicChannel::with_capacity(1);
+        b.send(0);
+        let sent = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                send_2pc(&a, 1, &b, 1);
+                sent.store(true, Ordering::Relaxed);
+            });
+            thread::sleep(Duration::from_millis(50));
+            assert!(!sent.load(Ordering::Relaxed));
+            // Only waiting on b, so a stays usable in the meantime
+            assert!(a.is_empty());
+            assert_eq!(b.receive(), 0);
+        });
+        assert_eq!((a.drain(), b.drain()), (vec![1], vec![1]));
+    }
+
+    // channel() always builds an unbounded one
+    fn bounded_channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+        let shared = Arc::new(Shared {
+            channel: BasicChannel::with_capacity(capacity),
+            senders: AtomicUsize::new(1),
+            receivers: AtomicUsize::new(1),
+            closed: AtomicBool::new(false),
+        });
+        let receiver = Receiver {
+            shared: shared.clone(),
+            #[cfg(feature = "linux")]
+            affinity: None,
+        };
+        (Sender { shared }, receiver)
+    }
+
+    #[test]
+    fn failover_drain_respects_a_bounded_backup() {
+        let primary = BasicChannel::new();
+        primary.send_all(0..5);
+        let (backup, backup_receiver) = bounded_channel(2);
+        thread::scope(|s| {
+            let consumer = s.spawn(|| {
+                let mut received = Vec::new();
+                while received.len() < 5 {
+                    assert!(backup.shared.channel.len() <= 2);
+                    received.push(backup_receiver.receive().unwrap());
+                }
+                received
+            });
+            assert!(primary.failover_drain(&backup).is_empty());
+            assert_eq!(consumer.join().unwrap(), [0, 1, 2, 3, 4]);
+        });
+    }
+
+    #[test]
+    fn failover_drain_gives_up_when_the_backup_receiver_leaves() {
+        let primary = BasicChannel::new();
+        primary.send_all(0..5);
+        let (backup, backup_receiver) = bounded_channel(2);
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                drop(backup_receiver);
+            });
+            assert_eq!(primary.failover_drain(&backup), [2, 3, 4]);
+        });
+    }
+
+    #[test]
+    fn failover_drain_refuses_a_closed_backup() {
+        let primary = BasicChannel::new();
+        primary.send_all(0..5);
+        let (backup, backup_receiver) = bounded_channel(2);
+        // Nothing queued yet, so there's nothing to wait for
+        assert!(backup.close_and_wait(Duration::ZERO));
+        assert_eq!(primary.failover_drain(&backup), [0, 1, 2, 3, 4]);
+        assert_eq!(backup_receiver.receive(), Err(RecvError::Disconnected));
+
+        // Closing while the drain is blocked on a full backup stops it too
+        primary.send_all(0..5);
+        let (backup, backup_receiver) = bounded_channel(2);
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                assert!(!backup.close_and_wait(Duration::ZERO));
+            });
+            assert_eq!(primary.failover_drain(&backup), [2, 3, 4]);
+        });
+        assert_eq!(backup_receiver.try_iter().collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn receive_disconnects_after_the_last_sender() {
+        let (sender, receiver) = channel();
+        let second = sender.clone();
+        sender.send(1);
+        drop(sender);
+        assert_eq!(receiver.receive(), Ok(1));
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                second.send(2);
+            });
+            assert_eq!(receiver.receive(), Ok(2));
+            // Blocks until the clone is dropped at the end of the spawned thread
+            assert_eq!(receiver.receive(), Err(RecvError::Disconnected));
+        });
+    }
+
+    #[test]
+    fn drain_fold_sums_the_backlog() {
+        let channel = BasicChannel::new();
+        channel.send_all([1, 2, 3, 4]);
+        assert_eq!(channel.drain_fold(0, |sum, n| sum + n), 10);
+        assert!(channel.is_empty());
+        assert_eq!(channel.drain_fold(0, |sum, n| sum + n), 0);
+    }
+
+    #[test]
+    fn receive_timeout_waits_the_full_duration() {
+        let channel = BasicChannel::<u32>::new();
+        let start = Instant::now();
+        assert_eq!(
+            channel.receive_timeout(Duration::from_millis(50)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(channel.try_receive(), None);
+    }
+
+    #[test]
+    fn receive_timeout_returns_a_message_sent_in_time() {
+        let channel = BasicChannel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                channel.send(1);
+            });
+            assert_eq!(channel.receive_timeout(Duration::from_secs(10)), Ok(1));
+        });
+        channel.send(2);
+        assert_eq!(channel.receive_timeout(Duration::ZERO), Ok(2));
+    }
+
+    #[test]
+    fn rejected_messages_go_to_the_dead_letter_channel() {
+        let (dead_letter, dead_letters) = channel();
+        let channel = BasicChannel::new().with_dead_letter(dead_letter.clone());
+        let ttl = Duration::from_secs(60);
+        assert!(channel.send_dedup_ttl(1, ttl));
+        assert!(!channel.send_dedup_ttl(1, ttl));
+        assert_eq!(channel.drain(), [1]);
+        assert_eq!(dead_letters.try_iter().collect::<Vec<_>>(), [1]);
+        // A closed dead-letter channel just means the message is dropped
+        assert!(dead_letter.close_and_wait(Duration::ZERO));
+        assert!(!channel.send_dedup_ttl(1, ttl));
+    }
+
+    #[test]
+    fn for_loop_ends_when_every_sender_is_gone() {
+        let (sender, receiver) = channel();
+        let producer = thread::spawn(move || {
+            for i in 0..3 {
+                sender.send(i);
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+        let mut received = Vec::new();
+        for message in receiver {
+            received.push(message);
+        }
+        producer.join().unwrap();
+        assert_eq!(received, [0, 1, 2]);
+    }
+
+    #[test]
+    fn try_iter_stops_at_an_empty_queue() {
+        let (sender, receiver) = channel();
+        sender.send(1);
+        sender.send(2);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(receiver.try_iter().next(), None);
+        sender.send(3);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn receive_batch_keeps_fifo_order() {
+        let channel = BasicChannel::new();
+        channel.send_all(0..5);
+        assert_eq!(channel.receive_batch(3), [0, 1, 2]);
+        assert_eq!(channel.receive_batch(10), [3, 4]);
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                channel.send_all([5, 6]);
+            });
+            // Blocks on the empty queue, then takes everything the batch send queued
+            assert_eq!(channel.receive_batch(10), [5, 6]);
+        });
+    }
+
+    #[test]
+    fn introspection_reports_a_snapshot() {
+        let unbounded = BasicChannel::new();
+        assert!(unbounded.is_empty());
+        assert_eq!(unbounded.capacity(), None);
+        assert_eq!(unbounded.remaining_capacity(), None);
+        unbounded.send(1);
+        assert_eq!(unbounded.len(), 1);
+        assert!(!unbounded.is_empty());
+
+        let bounded = BasicChannel::with_capacity(3);
+        bounded.send(1);
+        assert_eq!(bounded.capacity(), Some(3));
+        assert_eq!(bounded.remaining_capacity(), Some(2));
+        bounded.send_all([2, 3]);
+        assert_eq!(bounded.remaining_capacity(), Some(0));
+    }
+
+    #[test]
+    fn fair_mode_wakes_receivers_in_arrival_order() {
+        const RECEIVERS: usize = 16;
+        fn queued<T>(channel: &BasicChannel<T>) -> usize {
+            channel.fair_waiters.as_ref().unwrap().lock().unwrap().len()
+        }
+        for _ in 0..20 {
+            let channel = BasicChannel::new_fair();
+            thread::scope(|s| {
+                // Started one at a time, so receiver i is the i-th to start waiting
+                let receivers: Vec<_> = (0..RECEIVERS)
+                    .map(|i| {
+                        let receiver = s.spawn(|| channel.receive());
+                        while queued(&channel) < i + 1 {
+                            thread::yield_now();
+                        }
+                        receiver
+                    })
+                    .collect();
+                // One message at a time, each only once the previous one was taken, so no receiver can be beaten to its hand-off
+                for i in 0..RECEIVERS {
+                    channel.send(i);
+                    while queued(&channel) > RECEIVERS - i - 1 || !channel.is_empty() {
+                        thread::yield_now();
+                    }
+                }
+                for (i, receiver) in receivers.into_iter().enumerate() {
+                    assert_eq!(receiver.join().unwrap(), i);
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn send_all_wakes_every_waiting_receiver() {
+        const RECEIVERS: usize = 8;
+        let channel = BasicChannel::new();
+        thread::scope(|s| {
+            let receivers: Vec<_> = (0..RECEIVERS)
+                .map(|_| s.spawn(|| channel.receive()))
+                .collect();
+            while channel.blocked_receivers() < RECEIVERS {
+                thread::sleep(Duration::from_millis(1));
+            }
+            channel.send_all(0..RECEIVERS);
+            let mut received: Vec<_> = receivers.into_iter().map(|r| r.join().unwrap()).collect();
+            received.sort();
+            assert_eq!(received, (0..RECEIVERS).collect::<Vec<_>>());
+        });
+    }
+
+    #[test]
+    fn drain_takes_everything_without_blocking() {
+        let channel = BasicChannel::new();
+        assert!(channel.drain().is_empty());
+        channel.send_all(["a", "b"]);
+        channel.send("c");
+        assert_eq!(channel.drain(), ["a", "b", "c"]);
+        assert!(channel.is_empty());
+    }
+}
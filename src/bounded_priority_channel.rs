@@ -0,0 +1,162 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    sync::{Condvar, Mutex},
+};
+
+use crate::basic_channel::Sender;
+
+// Work scheduler channel: receive() hands out the highest priority message first (oldest first within a priority), and the total number of
+// queued messages across all priorities is capped.  What a send into a full channel does depends on the FullPolicy.  Messages are kept in
+// a BTreeMap ordered by (priority, Reverse(sequence number)), so the next message to receive is the last entry and the eviction candidate
+// is the first one
+pub struct BoundedPriorityChannel<T> {
+    queue: Mutex<Queue<T>>,
+    item_ready: Condvar,
+    space_ready: Condvar,
+    capacity: usize,
+    policy: FullPolicy,
+    // Gets the evicted or turned away messages instead of send() returning them
+    dead_letter: Option<Sender<T>>,
+}
+
+struct Queue<T> {
+    messages: BTreeMap<(u8, Reverse<u64>), T>,
+    next_seq: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FullPolicy {
+    /// send() waits until a receive makes room
+    Block,
+    /// send() makes room by evicting the lowest priority message (the newest one, if several share that priority), but only for a message of
+    /// strictly higher priority; anything else is turned away
+    EvictLowest,
+}
+
+impl<T> Queue<T> {
+    fn insert(&mut self, priority: u8, message: T) {
+        self.messages
+            .insert((priority, Reverse(self.next_seq)), message);
+        self.next_seq += 1;
+    }
+}
+
+impl<T> BoundedPriorityChannel<T> {
+    pub fn new(capacity: usize, policy: FullPolicy) -> Self {
+        assert!(capacity > 0, "capacity must be at least 1!");
+        Self {
+            queue: Mutex::new(Queue {
+                messages: BTreeMap::new(),
+                next_seq: 0,
+            }),
+            item_ready: Condvar::new(),
+            space_ready: Condvar::new(),
+            capacity,
+            policy,
+            dead_letter: None,
+        }
+    }
+
+    pub fn with_dead_letter(mut self, dead_letter: Sender<T>) -> Self {
+        self.dead_letter = Some(dead_letter);
+        self
+    }
+
+    /// Higher `priority` values are received first.  Returns whichever message didn't make it into the channel: the evicted one, or
+    /// `message` itself if it was turned away.  Always None with FullPolicy::Block, or when a dead-letter channel was configured, since
+    /// that's where the message goes instead (unless that channel has been closed)
+    pub fn send(&self, message: T, priority: u8) -> Option<T> {
+        let mut queue = self.queue.lock().unwrap();
+        while queue.messages.len() >= self.capacity {
+            match self.policy {
+                FullPolicy::Block => queue = self.space_ready.wait(queue).unwrap(),
+                FullPolicy::EvictLowest => {
+                    let lowest = queue.messages.first_entry().unwrap();
+                    if lowest.key().0 >= priority {
+                        drop(queue);
+                        return self.reject(message);
+                    }
+                    // The queue stays full, so there's no receiver to notify: none can be waiting
+                    let evicted = lowest.remove();
+                    queue.insert(priority, message);
+                    drop(queue);
+                    return self.reject(evicted);
+                }
+            }
+        }
+        queue.insert(priority, message);
+        drop(queue);
+        self.item_ready.notify_one();
+        None
+    }
+
+    fn reject(&self, message: T) -> Option<T> {
+        match &self.dead_letter {
+            Some(dead_letter) => dead_letter.try_send(message).err(),
+            None => Some(message),
+        }
+    }
+
+    pub fn receive(&self) -> T {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if let Some((_, message)) = queue.messages.pop_last() {
+                drop(queue);
+                self.space_ready.notify_one();
+                return message;
+            }
+            queue = self.item_ready.wait(queue).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_channel::channel;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn higher_priority_evicts_the_lowest() {
+        let channel = BoundedPriorityChannel::new(2, FullPolicy::EvictLowest);
+        assert_eq!(channel.send("low", 1), None);
+        assert_eq!(channel.send("mid", 5), None);
+        assert_eq!(channel.send("high", 9), Some("low"));
+        assert_eq!(channel.send("also low", 1), Some("also low"));
+        assert_eq!([channel.receive(), channel.receive()], ["high", "mid"]);
+    }
+
+    #[test]
+    fn eviction_goes_to_the_dead_letter_channel() {
+        let (sender, receiver) = channel();
+        let channel =
+            BoundedPriorityChannel::new(1, FullPolicy::EvictLowest).with_dead_letter(sender);
+        assert_eq!(channel.send(1, 1), None);
+        assert_eq!(channel.send(2, 2), None);
+        assert_eq!(receiver.receive(), Ok(1));
+        assert_eq!(channel.receive(), 2);
+    }
+
+    #[test]
+    fn block_waits_for_room() {
+        let channel = BoundedPriorityChannel::new(2, FullPolicy::Block);
+        channel.send(1, 0);
+        channel.send(2, 0);
+        let third_sent = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                channel.send(3, 9);
+                third_sent.store(true, Ordering::Relaxed);
+            });
+            thread::sleep(Duration::from_millis(50));
+            assert!(!third_sent.load(Ordering::Relaxed));
+            assert_eq!(channel.receive(), 1);
+        });
+        assert_eq!([channel.receive(), channel.receive()], [3, 2]);
+    }
+}
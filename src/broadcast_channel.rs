@@ -0,0 +1,292 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+};
+
+// Every subscribed receiver gets its own copy of every message sent after it subscribed.  Messages sit in a shared ring of at most
+// `capacity` slots, each one counting how many receivers still have to read it, so a slot is reclaimed as soon as the last of them has
+// passed it (and the last one gets the message itself instead of a clone).  A receiver that falls more than `capacity` messages behind has
+// the oldest ones evicted from under it, and finds out through RecvError::Lagged.  With with_replay(), the last `replay` messages are kept
+// around even once everyone has read them, so a new subscriber can catch up on them
+pub struct BroadcastChannel<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct BroadcastReceiver<T> {
+    shared: Arc<Shared<T>>,
+    // Index of the next message this receiver reads
+    next: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// This many messages were evicted before the receiver got to them.  The next receive() continues with the oldest one still around
+    Lagged(u64),
+    /// The BroadcastChannel is gone and this receiver has read everything that was sent
+    Closed,
+}
+
+struct Shared<T> {
+    ring: Mutex<Ring<T>>,
+    item_ready: Condvar,
+}
+
+// `slots` holds messages head..tail by index.  Receivers read in order, so the front slot always runs out of readers first: whatever a
+// later slot's readers have read, they read the front one before it.  Read slots only stay behind within the last `replay` of the ring, so
+// a slot running out of readers outside that window is always the front one
+struct Ring<T> {
+    slots: VecDeque<Slot<T>>,
+    head: u64,
+    tail: u64,
+    capacity: usize,
+    replay: usize,
+    receivers: usize,
+    closed: bool,
+}
+
+struct Slot<T> {
+    message: T,
+    unread: usize,
+}
+
+impl<T> Ring<T> {
+    fn reclaim(&mut self) {
+        while self.slots.len() > self.replay
+            && self.slots.front().is_some_and(|slot| slot.unread == 0)
+        {
+            self.slots.pop_front();
+            self.head += 1;
+        }
+    }
+
+    // Slot `i` is one of the last `replay`, which reclaim() keeps for new subscribers
+    fn kept_for_replay(&self, i: usize) -> bool {
+        self.slots.len() - i <= self.replay
+    }
+}
+
+impl<T> BroadcastChannel<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be at least 1!");
+        Self {
+            shared: Arc::new(Shared {
+                ring: Mutex::new(Ring {
+                    slots: VecDeque::with_capacity(capacity),
+                    head: 0,
+                    tail: 0,
+                    capacity,
+                    replay: 0,
+                    receivers: 0,
+                    closed: false,
+                }),
+                item_ready: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Every new subscriber starts with (up to) the last `replay` messages sent before it subscribed, oldest first, and then carries on with
+    /// live ones.  Panics if `replay` is more than the capacity, since the ring can't hold that many
+    pub fn with_replay(self, replay: usize) -> Self {
+        let mut ring = self.shared.ring.lock().unwrap();
+        assert!(
+            replay <= ring.capacity,
+            "can't replay more messages than the capacity!"
+        );
+        ring.replay = replay;
+        drop(ring);
+        self
+    }
+
+    /// The new receiver only sees messages sent from now on, plus the replayed ones if the channel has with_replay()
+    pub fn subscribe(&self) -> BroadcastReceiver<T> {
+        let mut ring = self.shared.ring.lock().unwrap();
+        ring.receivers += 1;
+        let replayed = ring.replay.min(ring.slots.len());
+        let start = ring.slots.len() - replayed;
+        for slot in ring.slots.iter_mut().skip(start) {
+            slot.unread += 1;
+        }
+        BroadcastReceiver {
+            shared: self.shared.clone(),
+            next: ring.tail - replayed as u64,
+        }
+    }
+
+    pub fn send(&self, message: T) {
+        let mut ring = self.shared.ring.lock().unwrap();
+        if ring.receivers == 0 && ring.replay == 0 {
+            // Nobody could ever read it, and an empty ring has nothing else to keep in order
+            ring.tail += 1;
+            ring.head = ring.tail;
+            return;
+        }
+        if ring.slots.len() == ring.capacity {
+            ring.slots.pop_front();
+            ring.head += 1;
+        }
+        let unread = ring.receivers;
+        ring.slots.push_back(Slot { message, unread });
+        ring.tail += 1;
+        // Pushing moves the replay window along, which can leave an already read slot behind it
+        ring.reclaim();
+        drop(ring);
+        self.shared.item_ready.notify_all();
+    }
+}
+
+impl<T> Drop for BroadcastChannel<T> {
+    fn drop(&mut self) {
+        self.shared.ring.lock().unwrap().closed = true;
+        self.shared.item_ready.notify_all();
+    }
+}
+
+impl<T: Clone> BroadcastReceiver<T> {
+    pub fn receive(&mut self) -> Result<T, RecvError> {
+        let mut ring = self.shared.ring.lock().unwrap();
+        loop {
+            if self.next < ring.head {
+                let skipped = ring.head - self.next;
+                self.next = ring.head;
+                return Err(RecvError::Lagged(skipped));
+            }
+            if self.next < ring.tail {
+                let i = (self.next - ring.head) as usize;
+                self.next += 1;
+                let kept = ring.kept_for_replay(i);
+                let slot = &mut ring.slots[i];
+                slot.unread -= 1;
+                if slot.unread > 0 || kept {
+                    return Ok(slot.message.clone());
+                }
+                // Last reader of the front slot (see Ring): the message can be moved out instead of cloned
+                let message = ring.slots.pop_front().unwrap().message;
+                ring.head += 1;
+                ring.reclaim();
+                return Ok(message);
+            }
+            if ring.closed {
+                return Err(RecvError::Closed);
+            }
+            ring = self.shared.item_ready.wait(ring).unwrap();
+        }
+    }
+}
+
+// Gives up this receiver's claim on everything it hasn't read yet, so those slots don't stay around waiting for it
+impl<T> Drop for BroadcastReceiver<T> {
+    fn drop(&mut self) {
+        let mut ring = self.shared.ring.lock().unwrap();
+        ring.receivers -= 1;
+        let start = self.next.saturating_sub(ring.head) as usize;
+        for slot in ring.slots.iter_mut().skip(start) {
+            slot.unread -= 1;
+        }
+        ring.reclaim();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn late_subscriber_gets_the_replayed_messages_first() {
+        let channel = BroadcastChannel::new(4).with_replay(2);
+        for i in 0..3 {
+            channel.send(i);
+        }
+        let mut receiver = channel.subscribe();
+        channel.send(3);
+        assert_eq!(receiver.receive(), Ok(1));
+        assert_eq!(receiver.receive(), Ok(2));
+        assert_eq!(receiver.receive(), Ok(3));
+    }
+
+    #[test]
+    fn replayed_messages_outlive_their_readers() {
+        let channel = BroadcastChannel::new(4).with_replay(2);
+        let mut early = channel.subscribe();
+        for i in 0..3 {
+            channel.send(i);
+        }
+        for i in 0..3 {
+            assert_eq!(early.receive(), Ok(i));
+        }
+        // Everything was read, but the last two are still there for whoever joins next
+        let mut late = channel.subscribe();
+        drop(channel);
+        assert_eq!(late.receive(), Ok(1));
+        assert_eq!(late.receive(), Ok(2));
+        assert_eq!(late.receive(), Err(RecvError::Closed));
+        assert_eq!(early.receive(), Err(RecvError::Closed));
+    }
+
+    #[test]
+    fn without_replay_subscribers_only_see_new_messages() {
+        let channel = BroadcastChannel::new(4);
+        channel.send(0);
+        let mut receiver = channel.subscribe();
+        channel.send(1);
+        drop(channel);
+        assert_eq!(receiver.receive(), Ok(1));
+        assert_eq!(receiver.receive(), Err(RecvError::Closed));
+    }
+
+    #[test]
+    fn every_receiver_sees_every_message() {
+        let channel = BroadcastChannel::new(4);
+        let receivers: Vec<_> = (0..3).map(|_| channel.subscribe()).collect();
+        std::thread::scope(|s| {
+            let readers: Vec<_> = receivers
+                .into_iter()
+                .map(|mut receiver| {
+                    s.spawn(move || {
+                        (0..10)
+                            .map(|_| receiver.receive().unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            for i in 0..10 {
+                channel.send(i);
+                // Keep the readers within the ring, so none of them lags
+                while channel.shared.ring.lock().unwrap().slots.len() == 4 {
+                    std::thread::yield_now();
+                }
+            }
+            for reader in readers {
+                assert_eq!(reader.join().unwrap(), (0..10).collect::<Vec<_>>());
+            }
+        });
+    }
+
+    #[test]
+    fn slow_receiver_lags() {
+        let channel = BroadcastChannel::new(2);
+        let mut receiver = channel.subscribe();
+        for i in 0..5 {
+            channel.send(i);
+        }
+        assert_eq!(receiver.receive(), Err(RecvError::Lagged(3)));
+        assert_eq!(receiver.receive(), Ok(3));
+        assert_eq!(receiver.receive(), Ok(4));
+    }
+
+    #[test]
+    fn slots_are_reclaimed_once_everyone_read_them() {
+        let channel = BroadcastChannel::new(4);
+        let mut first = channel.subscribe();
+        let mut second = channel.subscribe();
+        channel.send(String::from("message"));
+        assert_eq!(first.receive().as_deref(), Ok("message"));
+        assert_eq!(channel.shared.ring.lock().unwrap().slots.len(), 1);
+        assert_eq!(second.receive().as_deref(), Ok("message"));
+        assert!(channel.shared.ring.lock().unwrap().slots.is_empty());
+        // A receiver that leaves gives up its claim on what it hasn't read
+        channel.send(String::from("unread"));
+        drop(second);
+        assert_eq!(first.receive().as_deref(), Ok("unread"));
+        assert!(channel.shared.ring.lock().unwrap().slots.is_empty());
+    }
+}
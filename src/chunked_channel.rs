@@ -0,0 +1,174 @@
+use std::{
+    collections::LinkedList,
+    mem::MaybeUninit,
+    sync::{Condvar, Mutex},
+};
+
+// Addresses the reallocation downside of BasicChannel: instead of one VecDeque that doubles (and copies everything) whenever it fills up,
+// messages are stored in a linked list of fixed-size chunks.  Growing only ever allocates one more chunk of CHUNK slots, so a send never
+// has to stall every other thread while the whole queue is being moved, and a chunk is freed as soon as it has been read to the end
+const CHUNK: usize = 64;
+
+pub struct ChunkedChannel<T> {
+    chunks: Mutex<LinkedList<Chunk<T>>>,
+    item_ready: Condvar,
+}
+
+// Slots read..write hold initialized messages.  `write` only moves forward, so once a chunk is full new messages go into a new chunk
+struct Chunk<T> {
+    slots: Box<[MaybeUninit<T>; CHUNK]>,
+    read: usize,
+    write: usize,
+}
+
+impl<T> Chunk<T> {
+    fn new() -> Self {
+        Self {
+            slots: Box::new([const { MaybeUninit::uninit() }; CHUNK]),
+            read: 0,
+            write: 0,
+        }
+    }
+}
+
+impl<T> Drop for Chunk<T> {
+    fn drop(&mut self) {
+        for slot in &mut self.slots[self.read..self.write] {
+            unsafe { slot.assume_init_drop() }
+        }
+    }
+}
+
+impl<T> Default for ChunkedChannel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> ChunkedChannel<T> {
+    pub const fn new() -> Self {
+        Self {
+            chunks: Mutex::new(LinkedList::new()),
+            item_ready: Condvar::new(),
+        }
+    }
+
+    pub fn send(&self, message: T) {
+        let mut chunks = self.chunks.lock().unwrap();
+        if chunks.back().is_none_or(|chunk| chunk.write == CHUNK) {
+            chunks.push_back(Chunk::new());
+        }
+        let chunk = chunks.back_mut().unwrap();
+        chunk.slots[chunk.write].write(message);
+        chunk.write += 1;
+        drop(chunks);
+        self.item_ready.notify_one();
+    }
+
+    pub fn receive(&self) -> T {
+        let mut chunks = self.chunks.lock().unwrap();
+        loop {
+            if let Some(message) = Self::pop(&mut chunks) {
+                return message;
+            }
+            chunks = self.item_ready.wait(chunks).unwrap();
+        }
+    }
+
+    fn pop(chunks: &mut LinkedList<Chunk<T>>) -> Option<T> {
+        let chunk = chunks.front_mut()?;
+        if chunk.read == chunk.write {
+            // Only the last chunk can be partly written, so an empty front chunk means an empty queue
+            return None;
+        }
+        // Safety: slots before `write` are initialized, and moving `read` past the slot means it's never read (or dropped) again
+        let message = unsafe { chunk.slots[chunk.read].assume_init_read() };
+        chunk.read += 1;
+        if chunk.read == CHUNK {
+            chunks.pop_front();
+        }
+        Some(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+        thread,
+    };
+
+    // Passes everything through to the system allocator, but remembers the largest allocation made by a thread that opted in with
+    // track_allocations(), so tests running in parallel don't show up in it
+    struct LargestAllocation;
+
+    thread_local! {
+        static TRACKING: Cell<bool> = const { Cell::new(false) };
+        static LARGEST: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for LargestAllocation {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = TRACKING.try_with(|tracking| {
+                if tracking.get() {
+                    LARGEST.with(|largest| largest.set(largest.get().max(layout.size())));
+                }
+            });
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: LargestAllocation = LargestAllocation;
+
+    fn track_allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+        TRACKING.with(|tracking| tracking.set(true));
+        let result = f();
+        TRACKING.with(|tracking| tracking.set(false));
+        (result, LARGEST.with(Cell::take))
+    }
+
+    #[test]
+    fn growth_never_allocates_more_than_a_chunk() {
+        const MESSAGES: u32 = 1_000_000;
+        let channel = ChunkedChannel::new();
+        let chunk_size = size_of::<[MaybeUninit<u32>; CHUNK]>();
+        thread::scope(|s| {
+            let producer = s.spawn(|| {
+                track_allocations(|| {
+                    for i in 0..MESSAGES {
+                        channel.send(i);
+                    }
+                })
+                .1
+            });
+            let (sum, largest) = track_allocations(|| {
+                (0..MESSAGES)
+                    .map(|_| u64::from(channel.receive()))
+                    .sum::<u64>()
+            });
+            assert_eq!(sum, u64::from(MESSAGES) * u64::from(MESSAGES - 1) / 2);
+            assert!(largest <= chunk_size);
+            // The producer allocates every chunk, so it must have seen exactly that size as its largest one
+            assert_eq!(producer.join().unwrap(), chunk_size);
+        });
+    }
+
+    #[test]
+    fn unread_messages_are_dropped() {
+        let channel = ChunkedChannel::new();
+        let message = std::sync::Arc::new(());
+        for _ in 0..CHUNK + 1 {
+            channel.send(message.clone());
+        }
+        drop(channel.receive());
+        drop(channel);
+        assert_eq!(std::sync::Arc::strong_count(&message), 1);
+    }
+}
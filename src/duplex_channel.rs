@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use crate::{
+    basic_channel::BasicChannel,
+    os_channel::{self, Cancelled},
+};
+
+// Request/reply (RPC-style) on top of the existing primitives: requests travel over a BasicChannel, and each one carries its own one-shot
+// os_channel for the answer.  The client blocks in request() until the server replies through the ReplyPermit it got with the request
+pub struct DuplexChannel<T, U> {
+    requests: BasicChannel<(T, Arc<os_channel::Channel<U>>)>,
+}
+
+// The right to answer exactly one request.  Dropping it without replying cancels the one-shot, so the client's request() returns
+// Err(Cancelled) instead of waiting forever for an answer that can't come
+pub struct ReplyPermit<U> {
+    reply: Arc<os_channel::Channel<U>>,
+}
+
+impl<T, U> Default for DuplexChannel<T, U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, U> DuplexChannel<T, U> {
+    pub fn new() -> Self {
+        Self {
+            requests: BasicChannel::new(),
+        }
+    }
+
+    /// Sends `message` and blocks until the server replies to it, or returns Err(Cancelled) if the server dropped the ReplyPermit without
+    /// replying
+    pub fn request(&self, message: T) -> Result<U, Cancelled> {
+        let reply = Arc::new(os_channel::Channel::new());
+        self.requests.send((message, reply.clone()));
+        reply.receive_cancellable()
+    }
+
+    pub fn receive_request(&self) -> (T, ReplyPermit<U>) {
+        let (message, reply) = self.requests.receive();
+        (message, ReplyPermit { reply })
+    }
+}
+
+impl<U> ReplyPermit<U> {
+    pub fn reply(self, value: U) {
+        self.reply.send(value);
+    }
+}
+
+impl<U> Drop for ReplyPermit<U> {
+    fn drop(&mut self) {
+        // Fails (harmlessly) if reply() already sent the answer
+        let (_, cancel) = self.reply.split_with_cancel();
+        cancel.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn server_replies_through_the_permit() {
+        let channel = DuplexChannel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                let (request, permit) = channel.receive_request();
+                permit.reply(request * 2);
+            });
+            assert_eq!(channel.request(21), Ok(42));
+        });
+    }
+
+    #[test]
+    fn dropped_permit_cancels_the_request() {
+        let channel = DuplexChannel::<u32, u32>::new();
+        thread::scope(|s| {
+            s.spawn(|| drop(channel.receive_request()));
+            assert_eq!(channel.request(1), Err(Cancelled));
+        });
+    }
+}
@@ -1,4 +1,44 @@
+// The one-shot channels (os_channel and prime_channel) only need core and alloc, so they also build without std.  Everything built on
+// std's Mutex and Condvar (or that parks threads) is behind the default `std` feature
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+// The test harness needs std even where the library itself doesn't use it
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+#[cfg(feature = "std")]
+pub mod array_channel;
+#[cfg(feature = "std")]
+pub mod banded_channel;
+#[cfg(feature = "std")]
 pub mod basic_channel;
+#[cfg(feature = "std")]
+pub mod bounded_priority_channel;
+#[cfg(feature = "std")]
+pub mod broadcast_channel;
+#[cfg(feature = "std")]
+pub mod chunked_channel;
+#[cfg(feature = "std")]
+pub mod duplex_channel;
 pub mod os_channel;
+#[cfg(feature = "std")]
+pub mod paced_channel;
+#[cfg(feature = "std")]
+pub mod pool_channel;
 pub mod prime_channel;
+#[cfg(feature = "std")]
+pub mod priority_channel;
+#[cfg(feature = "std")]
 pub mod ref_channel;
+#[cfg(feature = "std")]
+pub mod scoped_ref_channel;
+#[cfg(feature = "std")]
+pub mod select;
+#[cfg(feature = "std")]
+pub mod signal_channel;
+#[cfg(feature = "std")]
+pub mod spsc_channel;
+mod sync;
+#[cfg(all(test, feature = "async"))]
+mod test_util;
@@ -1,31 +1,82 @@
+// Only needs core: std just adds the blocking receives (which park the thread), async receive, and the ref_channel conversions
+use core::mem::MaybeUninit;
+
+#[cfg(feature = "async")]
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+#[cfg(feature = "std")]
 use std::{
-    cell::UnsafeCell,
-    mem::MaybeUninit,
-    sync::atomic::{AtomicU8, Ordering},
+    hint, mem,
+    sync::Mutex,
+    thread::{self, Thread},
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "std")]
+use crate::ref_channel;
+use crate::sync::{AtomicU8, Ordering, UnsafeCell};
+
 // One-Shot Channel Impl
-const EMPTY: u8 = 0;
-const WRITING: u8 = 1;
-const READY: u8 = 2;
-const READING: u8 = 3;
+pub(crate) const EMPTY: u8 = 0;
+pub(crate) const WRITING: u8 = 1;
+pub(crate) const READY: u8 = 2;
+pub(crate) const READING: u8 = 3;
+const CANCELLED: u8 = 4;
+// The split() Sender was dropped without sending
+const CLOSED: u8 = 5;
 
 pub struct Channel<T> {
     message: UnsafeCell<MaybeUninit<T>>,
     // in_use: AtomicBool,
     // ready: AtomicBool,
     state: AtomicU8,
+    // Threads blocked in select_oneshot(), receive_blocking() or receive_cancellable() waiting on this channel.  This is how send (or cancel)
+    // finds someone to wake up.  A list rather than a single slot, since a select and a blocking receive can be waiting on the same channel
+    #[cfg(feature = "std")]
+    waiting: Mutex<Vec<Thread>>,
+    // Same thing for tasks awaiting receive_async() futures, one waker per pending future
+    #[cfg(feature = "async")]
+    wakers: Mutex<Vec<Waker>>,
 }
 
 unsafe impl<T> Sync for Channel<T> where T: Send {}
 
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Channel<T> {
+    #[cfg(not(loom))]
     pub const fn new() -> Self {
         Self {
             message: UnsafeCell::new(MaybeUninit::uninit()),
             // in_use: AtomicBool::new(false),
             // ready: AtomicBool::new(false),
             state: AtomicU8::new(EMPTY),
+            #[cfg(feature = "std")]
+            waiting: Mutex::new(Vec::new()),
+            #[cfg(feature = "async")]
+            wakers: Mutex::new(Vec::new()),
+        }
+    }
+
+    // loom's atomics can't be created in a const context
+    #[cfg(loom)]
+    pub fn new() -> Self {
+        Self {
+            message: UnsafeCell::new(MaybeUninit::uninit()),
+            // in_use: AtomicBool::new(false),
+            // ready: AtomicBool::new(false),
+            state: AtomicU8::new(EMPTY),
+            #[cfg(feature = "std")]
+            waiting: Mutex::new(Vec::new()),
+            #[cfg(feature = "async")]
+            wakers: Mutex::new(Vec::new()),
         }
     }
 
@@ -40,15 +91,177 @@ impl<T> Channel<T> {
         unsafe { (*self.message.get()).write(message) };
         self.ready.store(true, Ordering::Release); */
 
+        if self.try_send(message).is_err() {
+            panic!("can't send more than one message!");
+        }
+    }
+
+    /// Non-panicking send: if the channel was already used (or cancelled), the message comes straight back.  Losing the CAS means the
+    /// cell is never touched, so the caller gets back exactly what it passed in
+    pub fn try_send(&self, message: T) -> Result<(), T> {
         if self
             .state
             .compare_exchange(EMPTY, WRITING, Ordering::Relaxed, Ordering::Relaxed)
             .is_err()
         {
-            panic!("can't send more than one message!");
+            return Err(message);
         }
-        unsafe { (*self.message.get()).write(message) };
+        self.message
+            .with_mut(|message_ptr| unsafe { (*message_ptr).write(message) });
         self.state.store(READY, Ordering::Release);
+        self.wake();
+        Ok(())
+    }
+
+    /// Borrow-based counterpart of send/receive, like ref_channel::Channel::split(): the handles can only be used once each, and the borrow
+    /// checker rules out a second split while they're alive.  Resets the channel first, so it can be split again once the handles are gone
+    pub fn split(&mut self) -> (Sender<'_, T>, Receiver<'_, T>) {
+        // Assigning drops the old channel, including a message that was sent but never received
+        *self = Self::new();
+        (Sender { channel: self }, Receiver { channel: self })
+    }
+
+    /// Splits the sending capability in two: the OnceSender can only send and the CancelHandle can only cancel, so e.g. a timeout supervisor
+    /// can abort the hand-off without being able to forge a message.  Whichever of the two gets in first wins.  Unlike split() this takes
+    /// &self: the receiving side is the channel itself (receive_cancellable(), receive_async()), which has to stay usable from other threads
+    /// while the handles are alive, e.g. through the Arc a DuplexChannel request shares with its ReplyPermit.  Nothing stops splitting
+    /// again while earlier handles are alive, but that's harmless since every handle goes through the same one-time state transitions
+    pub fn split_with_cancel(&self) -> (OnceSender<'_, T>, CancelHandle<'_, T>) {
+        (OnceSender { channel: self }, CancelHandle { channel: self })
+    }
+
+    /// Parks the calling thread until a message has been sent, for a single hand-off between two threads without spinning on is_ready().
+    /// Panics if the channel was cancelled through its CancelHandle, since no message can arrive after that
+    #[cfg(feature = "std")]
+    pub fn receive_blocking(&self) -> T {
+        match self.receive_cancellable() {
+            Ok(message) => message,
+            Err(Cancelled) => panic!("channel was cancelled!"),
+        }
+    }
+
+    /// Blocks until a message arrives, or returns Err(Cancelled) once the channel has been cancelled through its CancelHandle
+    #[cfg(feature = "std")]
+    pub fn receive_cancellable(&self) -> Result<T, Cancelled> {
+        match self.park_until_done() {
+            Ok(message) => Ok(message),
+            Err(CANCELLED) => Err(Cancelled),
+            Err(_) => panic!("no message available!"),
+        }
+    }
+
+    /// Waits up to `dur` for a message without parking, so it doesn't need a receiver registered in `waiting`: spins at first, then
+    /// yields, then sleeps a little longer each round (never past the deadline).  A timeout leaves the channel exactly as it was, so the
+    /// message can still be received later.  A cancelled channel just times out, since no message can arrive
+    #[cfg(feature = "std")]
+    pub fn receive_timeout(&self, dur: Duration) -> Result<T, RecvTimeoutError> {
+        // Rounds 0..SPINS spin 2^round times, the next YIELDS rounds yield, and after that each round sleeps twice as long as the last, up
+        // to MAX_SLEEP
+        const SPINS: u32 = 6;
+        const YIELDS: u32 = 4;
+        const MAX_SLEEP: Duration = Duration::from_millis(1);
+        let deadline = Instant::now() + dur;
+        let mut round = 0;
+        loop {
+            if let Some(message) = self.try_receive() {
+                return Ok(message);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            if round < SPINS {
+                for _ in 0..1 << round {
+                    hint::spin_loop();
+                }
+            } else if round < SPINS + YIELDS {
+                thread::yield_now();
+            } else {
+                let sleep =
+                    Duration::from_micros(1 << (round - SPINS - YIELDS).min(10)).min(MAX_SLEEP);
+                thread::sleep(sleep.min(deadline - now));
+            }
+            round = round.saturating_add(1);
+        }
+    }
+
+    // Parks until the message can be taken, or returns the state that means it never will be: CANCELLED, CLOSED, or READING (somebody else
+    // already received it).  Same register-then-check order as select_oneshot(), so a send or cancel landing in between can't be missed
+    #[cfg(feature = "std")]
+    fn park_until_done(&self) -> Result<T, u8> {
+        let current = thread::current();
+        self.register_waiter(current.clone());
+        let result = loop {
+            match self
+                .state
+                .compare_exchange(READY, READING, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => break Ok(self.read_message()),
+                Err(state @ (CANCELLED | CLOSED | READING)) => break Err(state),
+                Err(_) => thread::park(),
+            }
+        };
+        self.unregister_waiter(&current);
+        result
+    }
+
+    #[cfg(feature = "std")]
+    fn register_waiter(&self, thread: Thread) {
+        self.waiting.lock().unwrap().push(thread);
+    }
+
+    // Usually a no-op: whatever woke the thread already took the whole list.  Matters when the thread returns without having been woken
+    // through this channel
+    #[cfg(feature = "std")]
+    fn unregister_waiter(&self, thread: &Thread) {
+        self.waiting
+            .lock()
+            .unwrap()
+            .retain(|waiting| waiting.id() != thread.id());
+    }
+
+    // Every transition out of EMPTY (sent, cancelled, closed) happens once, so each waiter only ever needs to be woken once
+    fn wake(&self) {
+        #[cfg(feature = "std")]
+        for thread in mem::take(&mut *self.waiting.lock().unwrap()) {
+            thread.unpark();
+        }
+        #[cfg(feature = "async")]
+        for waker in mem::take(&mut *self.wakers.lock().unwrap()) {
+            waker.wake();
+        }
+    }
+
+    /// Future that resolves to the message once it has been sent, for receiving inside an async task without blocking its thread.  Resolves
+    /// to an error instead once no message can arrive anymore: the split Sender was dropped, the channel was cancelled, or another receive
+    /// already took the message
+    #[cfg(feature = "async")]
+    pub fn receive_async(&self) -> ReceiveFuture<'_, T> {
+        ReceiveFuture { channel: self }
+    }
+
+    // A future polled again with the same waker (or another future of the same task) needs no second entry
+    #[cfg(feature = "async")]
+    fn register_waker(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    // Takes the message if it's there.  None while one could still arrive (EMPTY or WRITING), an error once it never will
+    #[cfg(feature = "async")]
+    fn poll_message(&self) -> Option<Result<T, RecvError>> {
+        match self
+            .state
+            .compare_exchange(READY, READING, Ordering::Acquire, Ordering::Relaxed)
+        {
+            Ok(_) => Some(Ok(self.read_message())),
+            Err(CLOSED) => Some(Err(RecvError::SenderDropped)),
+            Err(CANCELLED) => Some(Err(RecvError::Cancelled)),
+            Err(READING) => Some(Err(RecvError::AlreadyReceived)),
+            Err(_) => None,
+        }
     }
 
     // Ordering can now be relaxed because we have an acquire load flag in the receive method
@@ -61,26 +274,294 @@ impl<T> Channel<T> {
             panic!("no message available!")
         } */
 
+        match self.try_receive() {
+            Some(message) => message,
+            None => panic!("no message available!"),
+        }
+    }
+
+    /// Non-panicking receive: None unless the state is READY
+    pub fn try_receive(&self) -> Option<T> {
         if self
             .state
             .compare_exchange(READY, READING, Ordering::Acquire, Ordering::Relaxed)
             .is_err()
         {
-            panic!("no message available!");
+            return None;
+        }
+        Some(self.read_message())
+    }
+
+    // Only for whoever just won the READY -> READING CAS, which is what makes it the one and only read
+    fn read_message(&self) -> T {
+        self.message
+            .with(|message| unsafe { (*message).assume_init_read() })
+    }
+
+    #[cfg(feature = "std")]
+    fn with_message(message: T) -> Self {
+        Self {
+            message: UnsafeCell::new(MaybeUninit::new(message)),
+            state: AtomicU8::new(READY),
+            #[cfg(feature = "std")]
+            waiting: Mutex::new(Vec::new()),
+            #[cfg(feature = "async")]
+            wakers: Mutex::new(Vec::new()),
         }
-        unsafe { (*self.message.get()).assume_init_read() }
     }
+
+    // With exclusive access the only states left are EMPTY, READY, READING (already received), CANCELLED and CLOSED, since WRITING only
+    // lasts for the duration of a send() call.  Only READY has a message to move out; the channel is left EMPTY either way
+    #[cfg(feature = "std")]
+    fn take(&mut self) -> Option<T> {
+        if self.state.with_mut(|state| mem::replace(state, EMPTY)) != READY {
+            return None;
+        }
+        Some(self.read_message())
+    }
+}
+
+pub struct Sender<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+// Unlike ref_channel's Receiver this one can be moved to another thread: receive() registers whichever thread calls it
+pub struct Receiver<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// The Sender was dropped without sending, e.g. because its thread panicked first
+    SenderDropped,
+    /// The channel was cancelled through its CancelHandle
+    Cancelled,
+    /// The message was already taken by another receive
+    AlreadyReceived,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    Timeout,
+}
+
+impl<T> Sender<'_, T> {
+    pub fn send(self, message: T) {
+        self.channel.send(message);
+    }
+}
+
+// Closing only succeeds from EMPTY, so a Sender dropped right after send() (which is every one that sent) leaves the message alone
+impl<T> Drop for Sender<'_, T> {
+    fn drop(&mut self) {
+        if self
+            .channel
+            .state
+            .compare_exchange(EMPTY, CLOSED, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.channel.wake();
+        }
+    }
+}
+
+// A Receiver dropped without receiving leaves the message in the channel, where it's dropped along with the channel (or by the next split())
+impl<T> Receiver<'_, T> {
+    pub fn is_ready(&self) -> bool {
+        self.channel.is_ready()
+    }
+
+    /// Looks at the message without taking it, so a later receive() still gets it.  Sound because this Receiver is the only thing that
+    /// can move the message out of a split channel, and it can't while the returned reference borrows it.  Needs T: Sync because a shared
+    /// Receiver can be peeked from several threads at once, each holding a &T:
+    ///
+    /// ```compile_fail
+    /// use std::{cell::Cell, thread};
+    /// use channels::os_channel::Channel;
+    ///
+    /// let mut channel = Channel::new();
+    /// let (sender, receiver) = channel.split();
+    /// sender.send(Cell::new(0u64));
+    /// thread::scope(|s| {
+    ///     s.spawn(|| receiver.peek().unwrap().set(1));
+    ///     s.spawn(|| receiver.peek().unwrap().set(2));
+    /// });
+    /// ```
+    pub fn peek(&self) -> Option<&T>
+    where
+        T: Sync,
+    {
+        // Acquire pairs with the Release store of READY in try_send(), like the CAS in receive does
+        if self.channel.state.load(Ordering::Acquire) != READY {
+            return None;
+        }
+        Some(
+            self.channel
+                .message
+                .with(|message| unsafe { (*message).assume_init_ref() }),
+        )
+    }
+
+    /// Blocks until the Sender has sent, or returns Err(SenderDropped) if it was dropped without sending.  Panics if the message was
+    /// already taken with try_receive()
+    #[cfg(feature = "std")]
+    pub fn receive(self) -> Result<T, RecvError> {
+        match self.channel.park_until_done() {
+            Ok(message) => Ok(message),
+            Err(CLOSED) => Err(RecvError::SenderDropped),
+            Err(_) => panic!("no message available!"),
+        }
+    }
+
+    /// Never blocks: Ok(None) if nothing has been sent yet.  Takes &mut self so it can't run while a peek() reference is alive
+    pub fn try_receive(&mut self) -> Result<Option<T>, RecvError> {
+        match self.channel.state.compare_exchange(
+            READY,
+            READING,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => Ok(Some(self.channel.read_message())),
+            Err(CLOSED) => Err(RecvError::SenderDropped),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub struct ReceiveFuture<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+// Checked before registering so a message sent before the first poll resolves right away, and checked again after registering so a send
+// that lands in between (and found no waker to wake yet) isn't missed either.  Every pending future keeps its own waker, so the ones that
+// lose the race for the message still wake up to resolve to AlreadyReceived
+#[cfg(feature = "async")]
+impl<T> Future for ReceiveFuture<'_, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<T, RecvError>> {
+        let channel = self.channel;
+        if let Some(result) = channel.poll_message() {
+            return Poll::Ready(result);
+        }
+        channel.register_waker(cx.waker());
+        match channel.poll_message() {
+            Some(result) => {
+                channel
+                    .wakers
+                    .lock()
+                    .unwrap()
+                    .retain(|waker| !waker.will_wake(cx.waker()));
+                Poll::Ready(result)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+pub struct OnceSender<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+pub struct CancelHandle<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl<T> OnceSender<'_, T> {
+    /// Returns the message back if the channel was cancelled (or already used) first
+    pub fn send(self, message: T) -> Result<(), T> {
+        self.channel.try_send(message)
+    }
+}
+
+impl<T> CancelHandle<'_, T> {
+    /// Returns false if it was too late: a message was already sent (or is being written)
+    pub fn cancel(self) -> bool {
+        let cancelled = self
+            .channel
+            .state
+            .compare_exchange(EMPTY, CANCELLED, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok();
+        if cancelled {
+            self.channel.wake();
+        }
+        cancelled
+    }
+}
+
+// Conversions between this AtomicU8 state machine and the AtomicBool one-shot in ref_channel.  An unreceived message is carried over; a
+// channel that was empty (or whose message was already received) turns into a fresh, empty channel that can be sent on again
+#[cfg(feature = "std")]
+impl<T> From<ref_channel::Channel<T>> for Channel<T> {
+    fn from(mut channel: ref_channel::Channel<T>) -> Self {
+        match channel.take() {
+            Some(message) => Self::with_message(message),
+            None => Self::new(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> From<Channel<T>> for ref_channel::Channel<T> {
+    fn from(mut channel: Channel<T>) -> Self {
+        match channel.take() {
+            Some(message) => Self::with_message(message),
+            None => Self::new(),
+        }
+    }
+}
+
+/// Blocks until one of the one-shot channels has a message, and returns its index along with the message.  Only that one channel's message is
+/// received, the others are left as they are.  Channels that can't deliver anymore (cancelled, closed, or already received by someone else)
+/// are skipped, and None comes back once that's all of them
+#[cfg(feature = "std")]
+pub fn select_oneshot<T>(channels: &[&Channel<T>]) -> Option<(usize, T)> {
+    assert!(!channels.is_empty(), "can't select on zero channels!");
+    // Registering before checking the states is what prevents a lost wakeup: a send that stores READY after our check has to lock `waiting`
+    // afterwards, and will find (and unpark) this thread there
+    let current = thread::current();
+    for channel in channels {
+        channel.register_waiter(current.clone());
+    }
+    let selected = 'select: loop {
+        let mut pending = false;
+        for (i, channel) in channels.iter().enumerate() {
+            match channel.state.compare_exchange(
+                READY,
+                READING,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break 'select Some((i, channel.read_message())),
+                Err(EMPTY | WRITING) => pending = true,
+                Err(_) => {}
+            }
+        }
+        if !pending {
+            break None;
+        }
+        thread::park();
+    };
+    for channel in channels {
+        channel.unregister_waiter(&current);
+    }
+    selected
 }
 
 // An atomic operataion is not needed to check the atomic ready flag, because an object can only be dropped if it
 // is fully owned by whichever thread is dropping it, with no outstanding borrows.  This means we can use the AtomicBool::get_mut method,
-// which takes an exclusive reference (&mut self), proving atomic access is unnecessary.  The same holds for UnsafeCell, through UnsafeCell::get_mut
+// which takes an exclusive reference (&mut self), proving atomic access is unnecessary (crate::sync exposes it as with_mut, so loom can
+// follow along).  The same holds for the UnsafeCell
 impl<T> Drop for Channel<T> {
     fn drop(&mut self) {
-        if *self.state.get_mut() == READY {
-            unsafe {
-                self.message.get_mut().assume_init_drop();
-            }
+        if self.state.with_mut(|state| *state == READY) {
+            self.message
+                .with_mut(|message| unsafe { (*message).assume_init_drop() });
         }
     }
 }
@@ -104,3 +585,449 @@ pub fn _is_ready(self) -> bool {
 pub unsafe fn _receive(&self) -> T {
     (*self.message.get()).assume_init_read()
 } */
+
+// Only the core API, so these also run without std (cargo test --no-default-features)
+#[cfg(all(test, not(loom)))]
+mod core_tests {
+    use super::*;
+    use alloc::{string::String, sync::Arc};
+
+    #[test]
+    fn try_send_and_try_receive_never_panic() {
+        let channel = Channel::new();
+        assert_eq!(channel.try_receive(), None);
+        assert_eq!(channel.try_send(String::from("first")), Ok(()));
+        assert_eq!(
+            channel.try_send(String::from("second")),
+            Err(String::from("second"))
+        );
+        assert_eq!(channel.try_receive().as_deref(), Some("first"));
+        assert_eq!(channel.try_receive(), None);
+    }
+
+    #[test]
+    fn unread_message_is_dropped_with_the_channel() {
+        let message = Arc::new(());
+        let channel = Channel::new();
+        channel.send(message.clone());
+        drop(channel);
+        assert_eq!(Arc::strong_count(&message), 1);
+    }
+}
+
+#[cfg(all(test, feature = "std", not(loom)))]
+mod tests {
+    use super::*;
+    #[cfg(feature = "async")]
+    use crate::test_util::block_on;
+    use std::time::Duration;
+
+    #[test]
+    fn select_oneshot_returns_the_ready_channel() {
+        let channels = [Channel::new(), Channel::new(), Channel::new()];
+        let refs: Vec<_> = channels.iter().collect();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                channels[1].send("second");
+            });
+            assert_eq!(select_oneshot(&refs), Some((1, "second")));
+        });
+        assert!(!channels[0].is_ready() && !channels[2].is_ready());
+    }
+
+    #[test]
+    fn select_oneshot_skips_channels_that_cant_deliver() {
+        let cancelled = Channel::new();
+        assert!(cancelled.split_with_cancel().1.cancel());
+        let mut closed = Channel::new();
+        drop(closed.split());
+        let live = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                live.send(3);
+            });
+            assert_eq!(select_oneshot(&[&cancelled, &closed, &live]), Some((2, 3)));
+        });
+        assert_eq!(select_oneshot(&[&cancelled, &closed, &live]), None);
+    }
+
+    #[test]
+    fn select_oneshot_leaves_other_waiters_registered() {
+        let (a, b) = (Channel::new(), Channel::new());
+        thread::scope(|s| {
+            let blocked = s.spawn(|| a.receive_blocking());
+            // Give the blocking receive time to register on `a` before the select does
+            thread::sleep(Duration::from_millis(20));
+            b.send(2);
+            assert_eq!(select_oneshot(&[&a, &b]), Some((1, 2)));
+            a.send(1);
+            assert_eq!(blocked.join().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn conversions_carry_the_message_over() {
+        let mut atomic_bool = ref_channel::Channel::new();
+        atomic_bool.split().0.send(String::from("hi"));
+        let atomic_u8 = Channel::from(atomic_bool);
+        assert!(atomic_u8.is_ready());
+        assert_eq!(atomic_u8.receive(), "hi");
+
+        let sent = Channel::new();
+        sent.send(5);
+        // split() would reset it, so the message is checked directly
+        let mut back = ref_channel::Channel::from(sent);
+        assert_eq!(back.take(), Some(5));
+    }
+
+    #[test]
+    fn conversions_of_empty_or_received_channels_are_empty() {
+        let converted = Channel::<u8>::from(ref_channel::Channel::new());
+        assert!(!converted.is_ready());
+        converted.send(1);
+        assert_eq!(converted.receive(), 1);
+        // Already received: nothing carries over, and the result can be sent on again
+        let mut back = ref_channel::Channel::from(converted);
+        let (sender, receiver) = back.split();
+        sender.send(2);
+        assert_eq!(receiver.receive(), 2);
+    }
+
+    #[test]
+    fn cancel_wakes_the_receiver_and_fails_the_send() {
+        let channel = Channel::new();
+        let (sender, cancel) = channel.split_with_cancel();
+        thread::scope(|s| {
+            let receiver = s.spawn(|| channel.receive_cancellable());
+            thread::sleep(Duration::from_millis(20));
+            assert!(cancel.cancel());
+            assert_eq!(receiver.join().unwrap(), Err(Cancelled));
+        });
+        assert_eq!(sender.send(1), Err(1));
+    }
+
+    #[test]
+    fn losing_try_send_gets_its_own_message_back() {
+        let channel = Channel::new();
+        let results: Vec<_> = thread::scope(|s| {
+            let senders: Vec<_> = (0..4)
+                .map(|i| {
+                    s.spawn({
+                        let channel = &channel;
+                        move || (i, channel.try_send(vec![i; 16]))
+                    })
+                })
+                .collect();
+            senders
+                .into_iter()
+                .map(|sender| sender.join().unwrap())
+                .collect()
+        });
+        let mut winners = results.iter().filter(|(_, result)| result.is_ok());
+        let (winner, _) = winners.next().unwrap();
+        assert!(winners.next().is_none());
+        for (i, result) in &results {
+            if let Err(message) = result {
+                assert_eq!(*message, vec![*i; 16]);
+            }
+        }
+        assert_eq!(channel.receive(), vec![*winner; 16]);
+    }
+
+    #[test]
+    fn receive_blocking_parks_until_the_send() {
+        let channel = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                channel.send(1);
+            });
+            assert_eq!(channel.receive_blocking(), 1);
+        });
+    }
+
+    #[test]
+    fn receive_blocking_after_the_send_returns_right_away() {
+        let channel = Channel::new();
+        channel.send(2);
+        assert_eq!(channel.receive_blocking(), 2);
+    }
+
+    #[test]
+    fn receive_blocking_hand_off_never_hangs() {
+        for _ in 0..1000 {
+            let channel = Channel::new();
+            thread::scope(|s| {
+                s.spawn(|| channel.send(3));
+                assert_eq!(channel.receive_blocking(), 3);
+            });
+        }
+    }
+
+    #[test]
+    fn split_hands_off_one_message() {
+        let mut channel = Channel::new();
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(move || sender.send(String::from("hello")));
+            assert_eq!(receiver.receive().as_deref(), Ok("hello"));
+        });
+        // The previous handles are gone, so the channel can be split again
+        let (sender, mut receiver) = channel.split();
+        assert!(!receiver.is_ready());
+        sender.send(String::from("again"));
+        assert_eq!(receiver.peek().map(String::as_str), Some("again"));
+        assert_eq!(receiver.try_receive(), Ok(Some(String::from("again"))));
+    }
+
+    #[test]
+    fn unread_message_is_dropped_with_the_receiver() {
+        let message = std::sync::Arc::new(());
+        let mut channel = Channel::new();
+        {
+            let (sender, receiver) = channel.split();
+            sender.send(message.clone());
+            assert!(receiver.is_ready());
+        }
+        // The Receiver went out of scope without receiving.  Splitting again resets the channel, which drops the message left in it
+        let _ = channel.split();
+        assert_eq!(std::sync::Arc::strong_count(&message), 1);
+    }
+
+    #[test]
+    fn receive_fails_when_the_sender_is_dropped() {
+        let mut channel = Channel::<u32>::new();
+        let (sender, receiver) = channel.split();
+        drop(sender);
+        assert_eq!(receiver.receive(), Err(RecvError::SenderDropped));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn receive_async_is_woken_by_the_send() {
+        let channel = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                channel.send(1);
+            });
+            assert_eq!(block_on(channel.receive_async()), Ok(1));
+        });
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn receive_async_after_the_send_is_ready_on_the_first_poll() {
+        let channel = Channel::new();
+        channel.send(2);
+        let mut cx = Context::from_waker(Waker::noop());
+        let future = std::pin::pin!(channel.receive_async());
+        assert_eq!(future.poll(&mut cx), Poll::Ready(Ok(2)));
+    }
+
+    #[test]
+    fn send_then_drop_does_not_close() {
+        let mut channel = Channel::new();
+        let (sender, mut receiver) = channel.split();
+        sender.send(1);
+        assert_eq!(receiver.try_receive(), Ok(Some(1)));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn receive_async_resolves_when_the_sender_is_dropped() {
+        let mut channel = Channel::<u32>::new();
+        let (sender, _receiver) = channel.split();
+        let channel = sender.channel;
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                drop(sender);
+            });
+            assert_eq!(
+                block_on(channel.receive_async()),
+                Err(RecvError::SenderDropped)
+            );
+        });
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn receive_async_resolves_when_no_message_can_arrive() {
+        let cancelled = Channel::<u32>::new();
+        let (_, cancel) = cancelled.split_with_cancel();
+        assert!(cancel.cancel());
+        assert_eq!(
+            block_on(cancelled.receive_async()),
+            Err(RecvError::Cancelled)
+        );
+
+        let received = Channel::new();
+        received.send(1);
+        assert_eq!(received.receive(), 1);
+        assert_eq!(
+            block_on(received.receive_async()),
+            Err(RecvError::AlreadyReceived)
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn every_pending_receive_async_is_woken() {
+        struct Flag(std::sync::atomic::AtomicBool);
+        impl std::task::Wake for Flag {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
+        }
+        let flags = [(); 2].map(|_| std::sync::Arc::new(Flag(Default::default())));
+        let wakers = flags.each_ref().map(|flag| Waker::from(flag.clone()));
+        let channel = Channel::new();
+        let mut first = std::pin::pin!(channel.receive_async());
+        let mut second = std::pin::pin!(channel.receive_async());
+        assert!(
+            first
+                .as_mut()
+                .poll(&mut Context::from_waker(&wakers[0]))
+                .is_pending()
+        );
+        assert!(
+            second
+                .as_mut()
+                .poll(&mut Context::from_waker(&wakers[1]))
+                .is_pending()
+        );
+
+        channel.send(1);
+        assert!(
+            flags
+                .iter()
+                .all(|flag| flag.0.load(std::sync::atomic::Ordering::Relaxed))
+        );
+        assert_eq!(
+            second.poll(&mut Context::from_waker(&wakers[1])),
+            Poll::Ready(Ok(1))
+        );
+        assert_eq!(
+            first.poll(&mut Context::from_waker(&wakers[0])),
+            Poll::Ready(Err(RecvError::AlreadyReceived))
+        );
+    }
+
+    #[test]
+    fn peek_leaves_the_message_for_receive() {
+        let mut channel = Channel::new();
+        let (sender, receiver) = channel.split();
+        assert_eq!(receiver.peek(), None);
+        sender.send(vec![1, 2, 3]);
+        assert_eq!(receiver.peek().map(Vec::len), Some(3));
+        assert_eq!(receiver.peek(), Some(&vec![1, 2, 3]));
+        assert!(receiver.is_ready());
+        assert_eq!(receiver.receive(), Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn receive_timeout_leaves_the_channel_usable() {
+        let channel = Channel::new();
+        let start = Instant::now();
+        assert_eq!(
+            channel.receive_timeout(Duration::from_millis(30)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        // Timing out didn't touch the state, so the message can still be sent and received
+        channel.send(1);
+        assert_eq!(channel.receive_timeout(Duration::ZERO), Ok(1));
+        assert_eq!(
+            channel.receive_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+    }
+
+    #[test]
+    fn receive_timeout_gets_a_message_sent_in_time() {
+        let channel = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                channel.send(2);
+            });
+            assert_eq!(channel.receive_timeout(Duration::from_secs(10)), Ok(2));
+        });
+        assert_eq!(channel.try_receive(), None);
+    }
+}
+
+// Run with RUSTFLAGS="--cfg loom" cargo test --release --lib os_channel.  send() and receive() only panic when try_send() and try_receive()
+// fail, so the tests race those directly: loom checks every interleaving, and flags any access to the message cell that the state CAS
+// didn't order
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::{sync::Arc, thread};
+
+    #[test]
+    fn send_happens_before_receive() {
+        loom::model(|| {
+            let channel = Arc::new(Channel::new());
+            let sender = {
+                let channel = channel.clone();
+                thread::spawn(move || channel.send(String::from("hello")))
+            };
+            let message = loop {
+                match channel.try_receive() {
+                    Some(message) => break message,
+                    None => thread::yield_now(),
+                }
+            };
+            assert_eq!(message, "hello");
+            sender.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn only_one_send_wins() {
+        loom::model(|| {
+            let channel = Arc::new(Channel::new());
+            let senders: Vec<_> = [1, 2]
+                .into_iter()
+                .map(|n| {
+                    let channel = channel.clone();
+                    thread::spawn(move || {
+                        channel.try_send(Box::new(n)).err().map(|message| *message)
+                    })
+                })
+                .collect();
+            let rejected: Vec<_> = senders
+                .into_iter()
+                .filter_map(|sender| sender.join().unwrap())
+                .collect();
+            assert_eq!(rejected.len(), 1);
+            // The loser got its own message back, and the winner's is the one in the channel
+            let received = *channel.try_receive().unwrap();
+            assert_eq!(received + rejected[0], 3);
+        });
+    }
+
+    #[test]
+    fn premature_receive_sees_nothing_or_everything() {
+        loom::model(|| {
+            let channel = Arc::new(Channel::new());
+            let sender = {
+                let channel = channel.clone();
+                thread::spawn(move || channel.send(Box::new(42)))
+            };
+            let early = channel.try_receive();
+            sender.join().unwrap();
+            match early {
+                Some(message) => {
+                    assert_eq!(*message, 42);
+                    assert!(channel.try_receive().is_none());
+                }
+                None => assert_eq!(*channel.receive(), 42),
+            }
+        });
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::{
+    Condvar, Mutex,
+    atomic::{AtomicI64, Ordering},
+};
+
+use crate::basic_channel::BasicChannel;
+
+// Credit-based flow control on top of BasicChannel.  Every send spends one credit and blocks while there are none left, and the consumer hands
+// credits back with grant() once it has processed messages.  How far producers can run ahead is decided by the consumer instead of a fixed capacity
+pub struct PacedChannel<T> {
+    data: BasicChannel<T>,
+    credits: AtomicI64,
+    credit_lock: Mutex<()>,
+    credit_ready: Condvar,
+}
+
+impl<T> PacedChannel<T> {
+    pub fn new(credits: i64) -> Self {
+        Self {
+            data: BasicChannel::new(),
+            credits: AtomicI64::new(credits),
+            credit_lock: Mutex::new(()),
+            credit_ready: Condvar::new(),
+        }
+    }
+
+    pub fn send(&self, message: T) {
+        let mut credits = self.credits.load(Ordering::Relaxed);
+        loop {
+            if credits > 0 {
+                match self.credits.compare_exchange_weak(
+                    credits,
+                    credits - 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(current) => {
+                        credits = current;
+                        continue;
+                    }
+                }
+            }
+            // Out of credit: the check is repeated under the lock, so a grant() that lands between the load above and the wait below can't be missed
+            let mut guard = self.credit_lock.lock().unwrap();
+            while self.credits.load(Ordering::Relaxed) <= 0 {
+                guard = self.credit_ready.wait(guard).unwrap();
+            }
+            drop(guard);
+            credits = self.credits.load(Ordering::Relaxed);
+        }
+        self.data.send(message);
+    }
+
+    /// Called by the consumer after processing messages to let `n` more sends through
+    pub fn grant(&self, n: i64) {
+        self.credits.fetch_add(n, Ordering::Relaxed);
+        drop(self.credit_lock.lock().unwrap());
+        self.credit_ready.notify_all();
+    }
+
+    pub fn credits(&self) -> i64 {
+        self.credits.load(Ordering::Relaxed)
+    }
+
+    pub fn receive(&self) -> T {
+        self.data.receive()
+    }
+}
+
+// Relaxed ordering is enough for the credit counter since it doesn't guard any data: the messages themselves still go through the mutex of the
+// inner BasicChannel
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::atomic::AtomicBool, thread, time::Duration};
+
+    #[test]
+    fn third_send_waits_for_a_grant() {
+        let channel = PacedChannel::new(2);
+        channel.send(1);
+        channel.send(2);
+        assert_eq!(channel.credits(), 0);
+        let third_sent = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                channel.send(3);
+                third_sent.store(true, Ordering::Relaxed);
+            });
+            thread::sleep(Duration::from_millis(50));
+            assert!(!third_sent.load(Ordering::Relaxed));
+            channel.grant(1);
+        });
+        assert!(third_sent.load(Ordering::Relaxed));
+        assert_eq!(
+            [channel.receive(), channel.receive(), channel.receive()],
+            [1, 2, 3]
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use crate::basic_channel::{self, Receiver, Sender};
+
+// Channel for a pool of consumers where every consumer has its own sub-queue.  Instead of all of them contending on one queue, each message
+// is routed at send time to whichever consumer currently has the least pending work.  The queue lengths are only hints (another thread can
+// change them right after they're read), so the balancing is approximate
+pub struct PoolChannel<T> {
+    members: Vec<Sender<T>>,
+}
+
+impl<T> PoolChannel<T> {
+    /// Creates a pool of `size` sub-queues, returning the Receiver of each one to hand out to the consumers
+    pub fn new(size: usize) -> (Self, Vec<Receiver<T>>) {
+        assert!(size > 0, "a pool needs at least one receiver!");
+        let (members, receivers) = (0..size).map(|_| basic_channel::channel()).unzip();
+        (Self { members }, receivers)
+    }
+
+    pub fn send_to_pool(&self, message: T) {
+        // Ties go to the first of the least loaded members
+        let member = self
+            .members
+            .iter()
+            .min_by_key(|member| member.len_hint())
+            .unwrap();
+        member.send(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_go_to_the_least_loaded_member() {
+        let (pool, receivers) = PoolChannel::new(2);
+        for i in 0..4 {
+            pool.send_to_pool(i);
+        }
+        assert_eq!(receivers[0].len_hint(), 2);
+        assert_eq!(receivers[1].len_hint(), 2);
+        // Once the first consumer has caught up, new work goes to it until the loads are even again
+        assert_eq!(receivers[0].try_iter().collect::<Vec<_>>(), [0, 2]);
+        pool.send_to_pool(4);
+        pool.send_to_pool(5);
+        assert_eq!(receivers[0].try_iter().collect::<Vec<_>>(), [4, 5]);
+        assert_eq!(receivers[1].try_iter().collect::<Vec<_>>(), [1, 3]);
+    }
+}
@@ -1,10 +1,8 @@
-use std::{
+use alloc::sync::Arc;
+use core::{
     cell::UnsafeCell,
     mem::MaybeUninit,
-    sync::{
-        Arc,
-        atomic::{AtomicBool, Ordering},
-    },
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
@@ -40,9 +38,7 @@ impl<T> Drop for Channel<T> {
 impl<T> Sender<T> {
     pub fn send(self, message: T) {
         unsafe { (*self.channel.message.get()).write(message) };
-        self.channel
-            .ready
-            .store(true, std::sync::atomic::Ordering::Release);
+        self.channel.ready.store(true, Ordering::Release);
     }
 }
 
@@ -52,9 +48,33 @@ impl<T> Receiver<T> {
     }
 
     pub fn receive(self) -> T {
+        match self.try_receive() {
+            Some(message) => message,
+            None => panic!("no message available!"),
+        }
+    }
+
+    /// Non-panicking receive: None if no message has been sent yet.  Swapping `ready` back to false means a message can only be taken once
+    pub fn try_receive(&self) -> Option<T> {
         if !self.channel.ready.swap(false, Ordering::Acquire) {
-            panic!("no message available!");
+            return None;
         }
-        unsafe { (*self.channel.message.get()).assume_init_read() }
+        Some(unsafe { (*self.channel.message.get()).assume_init_read() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    #[test]
+    fn try_receive_takes_the_message_once() {
+        let (sender, receiver) = channel();
+        assert_eq!(receiver.try_receive(), None);
+        sender.send(String::from("message"));
+        assert!(receiver.is_ready());
+        assert_eq!(receiver.try_receive().as_deref(), Some("message"));
+        assert_eq!(receiver.try_receive(), None);
     }
 }
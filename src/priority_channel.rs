@@ -0,0 +1,115 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    sync::{Condvar, Mutex},
+};
+
+// Unbounded counterpart of BoundedPriorityChannel: receive() returns the highest priority message first, and messages of equal priority in
+// the order they were sent.  The max-heap is ordered by (priority, Reverse(sequence number)), so among equal priorities the message with the
+// lowest sequence number, i.e. the oldest one, is on top.  Same Mutex + Condvar pattern as BasicChannel otherwise
+pub struct PriorityChannel<T> {
+    queue: Mutex<Queue<T>>,
+    item_ready: Condvar,
+}
+
+struct Queue<T> {
+    heap: BinaryHeap<Entry<T>>,
+    next_seq: u64,
+}
+
+// Ordered by key only, so T doesn't need to implement Ord itself
+struct Entry<T> {
+    key: (u8, Reverse<u64>),
+    message: T,
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<T> Default for PriorityChannel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> PriorityChannel<T> {
+    pub const fn new() -> Self {
+        Self {
+            queue: Mutex::new(Queue {
+                heap: BinaryHeap::new(),
+                next_seq: 0,
+            }),
+            item_ready: Condvar::new(),
+        }
+    }
+
+    /// Higher `priority` values are received first
+    pub fn send(&self, message: T, priority: u8) {
+        let mut queue = self.queue.lock().unwrap();
+        let seq = queue.next_seq;
+        queue.next_seq += 1;
+        queue.heap.push(Entry {
+            key: (priority, Reverse(seq)),
+            message,
+        });
+        drop(queue);
+        self.item_ready.notify_one();
+    }
+
+    pub fn receive(&self) -> T {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if let Some(entry) = queue.heap.pop() {
+                return entry.message;
+            }
+            queue = self.item_ready.wait(queue).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn highest_priority_first_and_fifo_within_one() {
+        let channel = PriorityChannel::new();
+        channel.send("low", 1);
+        channel.send("high a", 9);
+        channel.send("mid", 5);
+        channel.send("high b", 9);
+        channel.send("high c", 9);
+        let received: Vec<_> = (0..5).map(|_| channel.receive()).collect();
+        assert_eq!(received, ["high a", "high b", "high c", "mid", "low"]);
+    }
+
+    #[test]
+    fn receive_blocks_until_a_send() {
+        let channel = PriorityChannel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                channel.send(1, 0);
+            });
+            assert_eq!(channel.receive(), 1);
+        });
+    }
+}
@@ -1,7 +1,7 @@
 use std::{
     cell::UnsafeCell,
     marker::PhantomData,
-    mem::MaybeUninit,
+    mem::{self, MaybeUninit},
     sync::atomic::{AtomicBool, Ordering},
     thread::{self, Thread},
 };
@@ -11,6 +11,12 @@ pub struct Channel<T> {
     ready: AtomicBool,
 }
 
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Channel<T> {
     pub const fn new() -> Self {
         Self {
@@ -33,6 +39,21 @@ impl<T> Channel<T> {
             },
         )
     }
+
+    pub(crate) fn with_message(message: T) -> Self {
+        Self {
+            message: UnsafeCell::new(MaybeUninit::new(message)),
+            ready: AtomicBool::new(true),
+        }
+    }
+
+    // Moves an unreceived message out, leaving the channel empty.  &mut self means no Sender or Receiver can be around
+    pub(crate) fn take(&mut self) -> Option<T> {
+        if !mem::replace(self.ready.get_mut(), false) {
+            return None;
+        }
+        Some(unsafe { self.message.get_mut().assume_init_read() })
+    }
 }
 
 unsafe impl<T> Sync for Channel<T> where T: Send {}
@@ -0,0 +1,49 @@
+use crate::basic_channel::BasicChannel;
+
+// Channel of shared references for scoped threads: instead of moving (or cloning) large data into the channel, senders pass &'a T and
+// receivers get the same reference back.  The 'a lifetime means everything sent has to outlive the channel itself, so a channel created
+// inside thread::scope can only carry references to data that lives beyond the scope, and the borrow checker rejects anything shorter.
+// T has to be Sync for the channel to be shared between the scoped threads, since the references end up being read on another thread
+pub struct ScopedRefChannel<'a, T> {
+    channel: BasicChannel<&'a T>,
+}
+
+impl<T> Default for ScopedRefChannel<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T> ScopedRefChannel<'a, T> {
+    pub fn new() -> Self {
+        Self {
+            channel: BasicChannel::new(),
+        }
+    }
+
+    pub fn send(&self, message: &'a T) {
+        self.channel.send(message);
+    }
+
+    pub fn receive(&self) -> &'a T {
+        self.channel.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn reference_to_a_stack_value_crosses_threads() {
+        let data = vec![1, 2, 3];
+        let channel = ScopedRefChannel::new();
+        thread::scope(|s| {
+            s.spawn(|| channel.send(&data));
+            let received = s.spawn(|| channel.receive()).join().unwrap();
+            assert!(std::ptr::eq(received, &data));
+            assert_eq!(received, &[1, 2, 3]);
+        });
+    }
+}
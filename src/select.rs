@@ -0,0 +1,95 @@
+use std::thread;
+
+use crate::basic_channel::BasicChannel;
+
+// Waits on several BasicChannels at once from a single thread.  The selecting thread registers itself with every channel, checks them all,
+// and parks until a send to any of them unparks it.  Channels are checked in the order they were added, so when several have messages the
+// earliest one wins
+pub struct Select<'a, T> {
+    channels: Vec<&'a BasicChannel<T>>,
+}
+
+impl<T> Default for Select<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T> Select<'a, T> {
+    pub fn new() -> Self {
+        Self {
+            channels: Vec::new(),
+        }
+    }
+
+    /// Adds `channel` to the set.  Its index in the results is the number of channels added before it
+    pub fn receive(mut self, channel: &'a BasicChannel<T>) -> Self {
+        self.channels.push(channel);
+        self
+    }
+
+    /// Blocks until one of the channels has a message, and returns its index along with the message.  Only that one message is received
+    pub fn wait(&self) -> (usize, T) {
+        assert!(!self.channels.is_empty(), "can't select on zero channels!");
+        let current = thread::current();
+        for channel in &self.channels {
+            channel.register_selector(current.clone());
+        }
+        let selected = loop {
+            if let Some(selected) = self.try_select() {
+                break selected;
+            }
+            thread::park();
+        };
+        for channel in &self.channels {
+            channel.unregister_selector(&current);
+        }
+        selected
+    }
+
+    /// Never blocks: None if every channel is empty right now
+    pub fn try_select(&self) -> Option<(usize, T)> {
+        self.channels
+            .iter()
+            .enumerate()
+            .find_map(|(i, channel)| Some((i, channel.try_receive()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn wait_returns_the_channel_that_got_a_message() {
+        let channels = [
+            BasicChannel::new(),
+            BasicChannel::new(),
+            BasicChannel::new(),
+        ];
+        let select = channels
+            .iter()
+            .fold(Select::new(), |select, channel| select.receive(channel));
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                channels[2].send("third");
+            });
+            assert_eq!(select.wait(), (2, "third"));
+        });
+        assert_eq!(select.try_select(), None);
+    }
+
+    #[test]
+    fn try_select_prefers_the_earliest_channel() {
+        let first = BasicChannel::new();
+        let second = BasicChannel::new();
+        second.send(2);
+        first.send(1);
+        let select = Select::new().receive(&first).receive(&second);
+        assert_eq!(select.try_select(), Some((0, 1)));
+        assert_eq!(select.try_select(), Some((1, 2)));
+        assert_eq!(select.try_select(), None);
+    }
+}
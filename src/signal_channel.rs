@@ -0,0 +1,96 @@
+use std::sync::{
+    Condvar, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
+
+// Channel for pure notifications (what a BasicChannel<()> would be used for), without any storage for messages: pending signals are just a
+// count.  send() increments it and receive() decrements it, blocking while it's zero.  The mutex doesn't protect any data, it's only
+// there so the Condvar can be used for blocking
+pub struct SignalChannel {
+    pending: AtomicUsize,
+    lock: Mutex<()>,
+    signal_ready: Condvar,
+}
+
+impl Default for SignalChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SignalChannel {
+    pub const fn new() -> Self {
+        Self {
+            pending: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            signal_ready: Condvar::new(),
+        }
+    }
+
+    pub fn send(&self) {
+        self.pending.fetch_add(1, Ordering::Release);
+        // Same idea as in PacedChannel::grant: a receiver that saw zero pending signals holds the lock until it's waiting on the Condvar
+        drop(self.lock.lock().unwrap());
+        self.signal_ready.notify_one();
+    }
+
+    pub fn try_receive(&self) -> bool {
+        self.pending
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    pub fn receive(&self) {
+        if self.try_receive() {
+            return;
+        }
+        let mut guard = self.lock.lock().unwrap();
+        while !self.try_receive() {
+            guard = self.signal_ready.wait(guard).unwrap();
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+}
+
+// Release/Acquire on the counter means whatever the sending thread did before send() is visible to the thread whose receive() consumed that
+// signal, just like it would be for a message passed through a channel
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::atomic::AtomicBool, thread, time::Duration};
+
+    #[test]
+    fn signals_are_counted() {
+        let channel = SignalChannel::new();
+        for _ in 0..3 {
+            channel.send();
+        }
+        assert_eq!(channel.pending(), 3);
+        for _ in 0..3 {
+            channel.receive();
+        }
+        assert_eq!(channel.pending(), 0);
+        assert!(!channel.try_receive());
+    }
+
+    #[test]
+    fn receive_blocks_until_a_signal() {
+        let channel = SignalChannel::new();
+        let received = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                channel.receive();
+                received.store(true, Ordering::Relaxed);
+            });
+            thread::sleep(Duration::from_millis(50));
+            assert!(!received.load(Ordering::Relaxed));
+            channel.send();
+        });
+        assert!(received.load(Ordering::Relaxed));
+        assert_eq!(channel.pending(), 0);
+    }
+}
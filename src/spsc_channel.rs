@@ -0,0 +1,126 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        Mutex,
+        atomic::{AtomicU8, Ordering},
+    },
+    thread::{self, Thread},
+};
+
+use crate::os_channel::{EMPTY, READING, READY, WRITING};
+
+// Reusable single-slot rendezvous between one producer and one consumer, on the same EMPTY -> WRITING -> READY -> READING state machine
+// as the one-shot os_channel::Channel, except that a receive puts the state back to EMPTY instead of leaving it at READING, so the slot
+// can be written again.  Nothing is allocated per message.  Each side parks while the slot is in the other side's hands
+pub struct SpscChannel<T> {
+    message: UnsafeCell<MaybeUninit<T>>,
+    state: AtomicU8,
+    // Threads parked in send_blocking() (waiting for the slot to be read) and receive_blocking() (waiting for it to be written).  One of
+    // each, which is what makes this single-producer single-consumer: a second waiter on the same side would overwrite the first
+    waiting_sender: Mutex<Option<Thread>>,
+    waiting_receiver: Mutex<Option<Thread>>,
+}
+
+unsafe impl<T> Sync for SpscChannel<T> where T: Send {}
+
+impl<T> Default for SpscChannel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SpscChannel<T> {
+    pub const fn new() -> Self {
+        Self {
+            message: UnsafeCell::new(MaybeUninit::uninit()),
+            state: AtomicU8::new(EMPTY),
+            waiting_sender: Mutex::new(None),
+            waiting_receiver: Mutex::new(None),
+        }
+    }
+
+    /// Blocks while the previous message hasn't been received yet
+    pub fn send_blocking(&self, message: T) {
+        // Registered before checking, like os_channel's receive_cancellable(), so a receive that empties the slot in between still finds
+        // this thread to unpark
+        *self.waiting_sender.lock().unwrap() = Some(thread::current());
+        // Acquire pairs with the Release store of EMPTY in receive_blocking(): the old message has been fully read out before the slot
+        // is overwritten
+        while self
+            .state
+            .compare_exchange(EMPTY, WRITING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            thread::park();
+        }
+        self.waiting_sender.lock().unwrap().take();
+        unsafe { (*self.message.get()).write(message) };
+        self.state.store(READY, Ordering::Release);
+        if let Some(thread) = self.waiting_receiver.lock().unwrap().take() {
+            thread.unpark();
+        }
+    }
+
+    /// Blocks until a message has been sent
+    pub fn receive_blocking(&self) -> T {
+        *self.waiting_receiver.lock().unwrap() = Some(thread::current());
+        while self
+            .state
+            .compare_exchange(READY, READING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            thread::park();
+        }
+        self.waiting_receiver.lock().unwrap().take();
+        let message = unsafe { (*self.message.get()).assume_init_read() };
+        self.state.store(EMPTY, Ordering::Release);
+        if let Some(thread) = self.waiting_sender.lock().unwrap().take() {
+            thread.unpark();
+        }
+        message
+    }
+}
+
+// Only READY has a message in the slot: a received one was moved out (and the state set back to EMPTY) by receive_blocking()
+impl<T> Drop for SpscChannel<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == READY {
+            unsafe { self.message.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn slot_is_reused_for_every_message() {
+        const MESSAGES: u32 = 10_000;
+        let channel = SpscChannel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..MESSAGES {
+                    channel.send_blocking(i);
+                }
+            });
+            for i in 0..MESSAGES {
+                assert_eq!(channel.receive_blocking(), i);
+            }
+        });
+    }
+
+    #[test]
+    fn pending_message_is_dropped_once() {
+        let message = Arc::new(());
+        let channel = SpscChannel::new();
+        channel.send_blocking(message.clone());
+        drop(channel.receive_blocking());
+        channel.send_blocking(message.clone());
+        assert_eq!(Arc::strong_count(&message), 2);
+        drop(channel);
+        assert_eq!(Arc::strong_count(&message), 1);
+    }
+}
@@ -0,0 +1,63 @@
+// The atomics and UnsafeCell os_channel is built on.  Normally these are thin wrappers around core's types, but building with `--cfg loom`
+// swaps in loom's instrumented versions, so the loom tests explore every interleaving of the exact code that runs in production.  The
+// wrappers only exist to give core's types the same API as loom's: UnsafeCell is accessed through with()/with_mut() closures (which is
+// how loom tracks each access), and exclusive access to an atomic goes through with_mut() instead of get_mut()
+
+#[cfg(loom)]
+pub(crate) use loom::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic::Ordering;
+
+#[cfg(not(loom))]
+pub(crate) struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    pub(crate) const fn new(data: T) -> Self {
+        Self(core::cell::UnsafeCell::new(data))
+    }
+
+    pub(crate) fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    pub(crate) fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
+#[cfg(not(loom))]
+pub(crate) struct AtomicU8(core::sync::atomic::AtomicU8);
+
+#[cfg(not(loom))]
+impl AtomicU8 {
+    pub(crate) const fn new(v: u8) -> Self {
+        Self(core::sync::atomic::AtomicU8::new(v))
+    }
+
+    pub(crate) fn load(&self, order: Ordering) -> u8 {
+        self.0.load(order)
+    }
+
+    pub(crate) fn store(&self, v: u8, order: Ordering) {
+        self.0.store(v, order)
+    }
+
+    pub(crate) fn compare_exchange(
+        &self,
+        current: u8,
+        new: u8,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<u8, u8> {
+        self.0.compare_exchange(current, new, success, failure)
+    }
+
+    pub(crate) fn with_mut<R>(&mut self, f: impl FnOnce(&mut u8) -> R) -> R {
+        f(self.0.get_mut())
+    }
+}